@@ -0,0 +1,109 @@
+//! A synchronous pool for scripts and CLI tools without an async runtime.
+//!
+//! `Pool` owns a current-thread tokio runtime and drives the async
+//! `bb8::Pool` on it, so pool maintenance only runs while a call blocks.
+//! Every method blocks the calling thread and panics if called from within
+//! an async runtime; async code should use `bb8::Pool` directly.
+
+use std::fmt;
+
+use bb8::PooledConnection;
+use libsql::{params::IntoParams, Value};
+use tokio::runtime::{self, Runtime};
+
+use crate::errors::ConnectionManagerError;
+use crate::LibsqlConnectionManager;
+
+/// A blocking pool of libsql connections.
+pub struct Pool {
+    runtime: Runtime,
+    pool: bb8::Pool<LibsqlConnectionManager>,
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool").field("pool", &self.pool).finish()
+    }
+}
+
+impl Pool {
+    /// Creates a pool with bb8's default settings.
+    pub fn new(manager: LibsqlConnectionManager) -> Result<Self, ConnectionManagerError> {
+        Self::with_builder(bb8::Pool::builder(), manager)
+    }
+
+    /// Creates a pool from a configured `bb8::Builder`.
+    pub fn with_builder(
+        builder: bb8::Builder<LibsqlConnectionManager>,
+        manager: LibsqlConnectionManager,
+    ) -> Result<Self, ConnectionManagerError> {
+        let runtime = runtime::Builder::new_current_thread().enable_all().build()?;
+        let pool = runtime.block_on(builder.build(manager))?;
+        Ok(Self { runtime, pool })
+    }
+
+    /// Checks out a connection, blocking until one is available.
+    pub fn get(&self) -> Result<Connection<'_>, ConnectionManagerError> {
+        let conn = self.runtime.block_on(self.pool.get())?;
+        Ok(Connection { runtime: &self.runtime, conn: Some(conn) })
+    }
+
+    /// Returns the underlying async pool.
+    pub fn pool(&self) -> &bb8::Pool<LibsqlConnectionManager> {
+        &self.pool
+    }
+}
+
+/// A connection checked out of a blocking `Pool`, returned to it on drop.
+pub struct Connection<'a> {
+    runtime: &'a Runtime,
+    conn: Option<PooledConnection<'a, LibsqlConnectionManager>>,
+}
+
+impl fmt::Debug for Connection<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Connection").field("conn", &self.conn).finish()
+    }
+}
+
+impl Connection<'_> {
+    /// Runs `sql` and collects the column values of every row. Values are read
+    /// eagerly because a local `libsql::Row` is only valid until the next row.
+    /// See `LibsqlConnection::query`
+    pub fn query(&self, sql: &str, params: impl IntoParams) -> Result<Vec<Vec<Value>>, ConnectionManagerError> {
+        let conn = self.conn();
+        self.runtime.block_on(async {
+            let mut rows = conn.query(sql, params).await?;
+            let mut collected = Vec::new();
+            while let Some(row) = rows.next().await? {
+                let values = (0..rows.column_count())
+                    .map(|idx| row.get_value(idx))
+                    .collect::<Result<Vec<_>, _>>()?;
+                collected.push(values);
+            }
+            Ok(collected)
+        })
+    }
+
+    /// See `LibsqlConnection::execute`
+    pub fn execute(&self, sql: &str, params: impl IntoParams) -> Result<u64, ConnectionManagerError> {
+        self.runtime.block_on(self.conn().execute(sql, params))
+    }
+
+    /// See `LibsqlConnection::execute_batch`
+    pub fn execute_batch(&self, sql: &str) -> Result<(), ConnectionManagerError> {
+        self.runtime.block_on(self.conn().execute_batch(sql))
+    }
+
+    fn conn(&self) -> &PooledConnection<'_, LibsqlConnectionManager> {
+        self.conn.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        // Returning the connection may spawn pool tasks, which need the runtime.
+        let _guard = self.runtime.enter();
+        drop(self.conn.take());
+    }
+}
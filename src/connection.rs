@@ -0,0 +1,561 @@
+//! The connection type handed out by `LibsqlConnectionManager`.
+
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::{BoxStream, StreamExt};
+use libsql::{params::{IntoParams, Params}, Connection, Row, Rows};
+use tokio::sync::Semaphore;
+
+use crate::errors::ConnectionManagerError;
+use crate::row::FromLibsqlRow;
+
+pub(crate) type QueryLogger = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+
+/// The row limit set by `LibsqlConnectionManager::max_rows`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RowLimit {
+    pub(crate) max_rows: usize,
+    pub(crate) truncate: bool,
+}
+
+/// The attributes of the spans emitted with the `otel` feature.
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QuerySpans {
+    pub(crate) variant: &'static str,
+    pub(crate) redact: bool,
+}
+
+/// A pooled `libsql::Connection`.
+///
+/// Derefs to `libsql::Connection`, so it can be used anywhere a connection
+/// is expected. `query`, `execute` and `execute_batch` are wrapped to run the
+/// manager's query hooks, such as `LibsqlConnectionManager::query_logger`,
+/// and so are the helpers in `query`, which take a `LibsqlConnection`.
+/// `changes` and `total_changes` report the rows changed by the last
+/// statement and by this connection since it was opened.
+///
+/// With the `otel` feature, each wrapped statement runs in a `libsql.query`
+/// tracing span carrying OpenTelemetry's database attributes (`db.system`,
+/// `db.statement`, and `libsql.variant` naming the manager's variant), for
+/// export with `tracing-opentelemetry`. A local `query` only prepares the
+/// statement, so its span does not cover reading the rows.
+pub struct LibsqlConnection {
+    pub(crate) conn: Connection,
+    query_logger: Option<QueryLogger>,
+    request_limit: Option<Arc<Semaphore>>,
+    row_limit: Option<RowLimit>,
+    max_parameters: Option<usize>,
+    #[cfg(feature = "otel")]
+    spans: Option<QuerySpans>,
+    pub(crate) last_validated: Option<Instant>,
+    /// Whether this is a replica connection, which rejects pragma setters.
+    pub(crate) replica: bool,
+    activity: Option<Arc<Activity>>,
+    _keepalive: Option<KeepAlive>,
+}
+
+/// Pings a connection every `LibsqlConnectionManager::keepalive_interval`
+/// while it is idle in the pool, until dropped.
+struct KeepAlive {
+    task: tokio::task::JoinHandle<()>,
+    // Set when the connection is returned to the pool and cleared when it is
+    // checked out, so pings never run alongside a borrower's statements.
+    idle: Arc<AtomicBool>,
+    // Set for pools that do not report returns, such as deadpool: the
+    // connection then counts as idle once no statement has run for an interval.
+    #[cfg(feature = "deadpool")]
+    idle_when_quiet: Arc<AtomicBool>,
+    activity: Arc<Activity>,
+    pings: Arc<AtomicU64>,
+}
+
+/// The statements running on a connection with a keepalive, shared with the
+/// handles `share` makes.
+struct Activity {
+    // Held for the length of a ping, so a checkout or a statement can wait
+    // one out.
+    ping: tokio::sync::Mutex<()>,
+    running: AtomicUsize,
+    last_used: std::sync::Mutex<Instant>,
+}
+
+impl Activity {
+    fn is_quiet_for(&self, interval: Duration) -> bool {
+        self.running.load(Ordering::SeqCst) == 0 && self.last_used.lock().unwrap().elapsed() >= interval
+    }
+}
+
+/// Counts a statement as running on a connection until dropped.
+struct Running<'a>(&'a Activity);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        *self.0.last_used.lock().unwrap() = Instant::now();
+        let _ = self.0.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl KeepAlive {
+    fn spawn(conn: Connection, interval: Duration) -> Self {
+        let idle = Arc::new(AtomicBool::new(false));
+        let idle_when_quiet = Arc::new(AtomicBool::new(false));
+        let activity = Arc::new(Activity {
+            ping: tokio::sync::Mutex::new(()),
+            running: AtomicUsize::new(0),
+            last_used: std::sync::Mutex::new(Instant::now()),
+        });
+        let pings = Arc::new(AtomicU64::new(0));
+        let task = {
+            let idle = idle.clone();
+            let idle_when_quiet = idle_when_quiet.clone();
+            let activity = activity.clone();
+            let pings = pings.clone();
+            tokio::spawn(async move {
+                let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    let _ = ticks.tick().await;
+                    let _ping = activity.ping.lock().await;
+                    let is_idle = idle.load(Ordering::SeqCst)
+                        || (idle_when_quiet.load(Ordering::SeqCst) && activity.is_quiet_for(interval));
+                    if !is_idle || !conn.is_autocommit() {
+                        continue;
+                    }
+                    // A failed ping is left for `is_valid` to catch on checkout.
+                    if let Err(_err) = conn.execute_batch("SELECT 1;").await {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(error = %_err, "libsql keepalive ping failed");
+                    }
+                    let _ = pings.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        };
+        Self {
+            task,
+            idle,
+            #[cfg(feature = "deadpool")]
+            idle_when_quiet,
+            activity,
+            pings,
+        }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl fmt::Debug for LibsqlConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut builder = f.debug_struct("LibsqlConnection");
+        let _ = builder.field("query_logger", &self.query_logger.is_some());
+        let _ = builder.field("request_limit", &self.request_limit.as_ref().map(|limit| limit.available_permits()));
+        let _ = builder.field("row_limit", &self.row_limit);
+        let _ = builder.field("max_parameters", &self.max_parameters);
+        let _ = builder.field("last_validated", &self.last_validated);
+        let _ = builder.field("replica", &self.replica);
+        let _ = builder.field("keepalive_pings", &self._keepalive.as_ref().map(|keepalive| keepalive.pings.load(Ordering::Relaxed)));
+        builder.finish()
+    }
+}
+
+impl LibsqlConnection {
+    pub(crate) fn new(
+        conn: Connection,
+        query_logger: Option<QueryLogger>,
+        max_concurrent_requests: Option<usize>,
+        row_limit: Option<RowLimit>,
+        max_parameters: Option<usize>,
+        keepalive_interval: Option<Duration>,
+    ) -> Self {
+        let request_limit = max_concurrent_requests.map(|limit| Arc::new(Semaphore::new(limit)));
+        let keepalive = keepalive_interval.map(|interval| KeepAlive::spawn(conn.clone(), interval));
+        let activity = keepalive.as_ref().map(|keepalive| keepalive.activity.clone());
+        Self {
+            conn,
+            query_logger,
+            request_limit,
+            row_limit,
+            max_parameters,
+            #[cfg(feature = "otel")]
+            spans: None,
+            last_validated: None,
+            replica: false,
+            activity,
+            _keepalive: keepalive,
+        }
+    }
+
+    /// Returns a handle to the same underlying connection with the same query
+    /// hooks, for a transaction or a callback to run statements on.
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            query_logger: self.query_logger.clone(),
+            request_limit: self.request_limit.clone(),
+            row_limit: self.row_limit,
+            max_parameters: self.max_parameters,
+            #[cfg(feature = "otel")]
+            spans: self.spans,
+            last_validated: None,
+            replica: self.replica,
+            activity: self.activity.clone(),
+            _keepalive: None,
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn with_spans(mut self, spans: QuerySpans) -> Self {
+        self.spans = Some(spans);
+        self
+    }
+
+    /// See `libsql::Connection::query`
+    pub async fn query(&self, sql: &str, params: impl IntoParams) -> Result<Rows, ConnectionManagerError> {
+        let params = self.check_parameters(params)?;
+        Ok(self.run(sql, self.conn.query(sql, params)).await?)
+    }
+
+    /// See `libsql::Connection::execute`
+    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> Result<u64, ConnectionManagerError> {
+        let params = self.check_parameters(params)?;
+        Ok(self.run(sql, self.conn.execute(sql, params)).await?)
+    }
+
+    /// See `libsql::Connection::execute_batch`. The whole batch is logged and
+    /// traced as one statement.
+    pub async fn execute_batch(&self, sql: &str) -> Result<(), ConnectionManagerError> {
+        let _ = self.run(sql, self.conn.execute_batch(sql)).await?;
+        Ok(())
+    }
+
+    /// Runs `sql` and maps every row with `T::from_row`, subject to
+    /// `LibsqlConnectionManager::max_rows`. Same as `query::query_as`.
+    pub async fn query_all<T: FromLibsqlRow>(&self, sql: &str, params: impl IntoParams) -> Result<Vec<T>, ConnectionManagerError> {
+        let mut rows = self.query(sql, params).await?;
+        let mut values = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if !self.admit_row(values.len())? {
+                break;
+            }
+            values.push(T::from_row(&row)?);
+        }
+        Ok(values)
+    }
+
+    /// Runs `sql` and returns its rows as a `Stream`, subject to
+    /// `LibsqlConnectionManager::max_rows`: the stream ends at the limit, with
+    /// a `TooManyRows` error unless truncation is enabled. Same as
+    /// `query::query_stream`.
+    pub async fn query_stream(
+        &self,
+        sql: &str,
+        params: impl IntoParams,
+    ) -> Result<BoxStream<'static, Result<Row, ConnectionManagerError>>, ConnectionManagerError> {
+        let rows = self.query(sql, params).await?;
+        let row_limit = self.row_limit;
+        // `try_unfold` ends the stream after the first error.
+        Ok(futures::stream::try_unfold((rows, 0), move |(mut rows, read)| async move {
+            let row = match rows.next().await? {
+                Some(row) => row,
+                None => return Ok(None),
+            };
+            if !admit_row(row_limit, read)? {
+                return Ok(None);
+            }
+            Ok(Some((row, (rows, read + 1))))
+        }).boxed())
+    }
+
+    /// Applies `LibsqlConnectionManager::max_rows` to a row just fetched, with
+    /// `read` rows already taken: `Ok(false)` ends the read at a truncating
+    /// limit, and a failing limit returns `TooManyRows`. Every helper that
+    /// reads rows goes through this.
+    pub(crate) fn admit_row(&self, read: usize) -> Result<bool, ConnectionManagerError> {
+        admit_row(self.row_limit, read)
+    }
+
+    /// Marks the connection as returned to the pool, which starts its
+    /// keepalive pings.
+    pub(crate) fn set_idle(&self) {
+        if let Some(keepalive) = &self._keepalive {
+            keepalive.idle.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Marks the connection as checked out, which stops its keepalive pings,
+    /// and waits for a ping in flight to finish.
+    pub(crate) async fn set_checked_out(&self) {
+        if let Some(keepalive) = &self._keepalive {
+            keepalive.idle.store(false, Ordering::SeqCst);
+            drop(keepalive.activity.ping.lock().await);
+        }
+    }
+
+    /// Lets keepalive pings run once no wrapped statement has run for an
+    /// interval, for pools that do not report when a connection is returned.
+    #[cfg(feature = "deadpool")]
+    pub(crate) fn set_idle_when_quiet(&self) {
+        if let Some(keepalive) = &self._keepalive {
+            keepalive.idle_when_quiet.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Counts a statement as running for the keepalive, after waiting for a
+    /// ping in flight to finish.
+    async fn start_running(&self) -> Option<Running<'_>> {
+        let activity = self.activity.as_deref()?;
+        // Counted before waiting, so a ping that takes the lock next skips.
+        let _ = activity.running.fetch_add(1, Ordering::SeqCst);
+        let running = Running(activity);
+        drop(activity.ping.lock().await);
+        Some(running)
+    }
+
+    /// Unwraps the underlying `libsql::Connection`.
+    pub fn into_inner(self) -> Connection {
+        self.conn
+    }
+
+    /// Runs `statement`, a future running `sql` on this connection, under the
+    /// query hooks: the request limit, the `otel` span and the query logger.
+    pub(crate) async fn run<T>(&self, sql: &str, statement: impl Future<Output = libsql::Result<T>>) -> libsql::Result<T> {
+        let _permit = self.acquire().await;
+        let _running = self.start_running().await;
+        let started = Instant::now();
+        let result = self.instrument(sql, statement).await;
+        self.log(sql, started);
+        result
+    }
+
+    async fn acquire(&self) -> Option<tokio::sync::SemaphorePermit<'_>> {
+        match &self.request_limit {
+            // The semaphore is never closed, so acquiring cannot fail.
+            Some(limit) => limit.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Enforces `LibsqlConnectionManager::max_parameters` on `params`,
+    /// returning them converted for libsql.
+    pub(crate) fn check_parameters(&self, params: impl IntoParams) -> Result<Params, ConnectionManagerError> {
+        let params = params.into_params()?;
+        let count = match &params {
+            Params::None => 0,
+            Params::Positional(values) => values.len(),
+            Params::Named(values) => values.len(),
+        };
+        match self.max_parameters {
+            Some(limit) if count > limit => {
+                Err(ConnectionManagerError::TooManyParameters(count, limit))
+            },
+            _ => Ok(params),
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    async fn instrument<T>(&self, sql: &str, statement: impl Future<Output = libsql::Result<T>>) -> libsql::Result<T> {
+        use tracing::Instrument;
+
+        let spans = match self.spans {
+            Some(spans) => spans,
+            None => return statement.await,
+        };
+        let span = tracing::info_span!(
+            "libsql.query",
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            otel.status_message = tracing::field::Empty,
+            db.system = "sqlite",
+            db.statement = tracing::field::Empty,
+            libsql.variant = spans.variant,
+        );
+        if spans.redact {
+            span.record("db.statement", redact_statement(sql).as_str());
+        } else {
+            span.record("db.statement", sql);
+        }
+
+        let result = statement.instrument(span.clone()).await;
+        if let Err(err) = &result {
+            span.record("otel.status_code", "ERROR");
+            span.record("otel.status_message", tracing::field::display(err));
+        }
+        result
+    }
+
+    #[cfg(not(feature = "otel"))]
+    async fn instrument<T>(&self, _sql: &str, statement: impl Future<Output = libsql::Result<T>>) -> libsql::Result<T> {
+        statement.await
+    }
+
+    fn log(&self, sql: &str, started: Instant) {
+        if let Some(query_logger) = &self.query_logger {
+            query_logger(sql, started.elapsed());
+        }
+    }
+}
+
+fn admit_row(row_limit: Option<RowLimit>, read: usize) -> Result<bool, ConnectionManagerError> {
+    match row_limit {
+        Some(limit) if read >= limit.max_rows => match limit.truncate {
+            true => Ok(false),
+            false => Err(ConnectionManagerError::TooManyRows(limit.max_rows)),
+        },
+        _ => Ok(true),
+    }
+}
+
+/// Replaces the string and number literals of `sql` with `?`.
+#[cfg(feature = "otel")]
+fn redact_statement(sql: &str) -> String {
+    let mut redacted = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    // Whether the previous character continues an identifier, so the digits
+    // of names such as `t1` and parameters such as `?1` are kept.
+    let mut in_word = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // A doubled quote is an escaped quote inside the literal.
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                redacted.push('?');
+                in_word = false;
+            },
+            '"' | '`' => {
+                redacted.push(c);
+                for quoted in chars.by_ref() {
+                    redacted.push(quoted);
+                    if quoted == c {
+                        break;
+                    }
+                }
+                in_word = false;
+            },
+            c if c.is_ascii_digit() && !in_word => {
+                while chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.').is_some() {}
+                redacted.push('?');
+            },
+            c => {
+                redacted.push(c);
+                in_word = c.is_alphanumeric() || matches!(c, '_' | '$' | '?' | ':' | '@');
+            },
+        }
+    }
+    redacted
+}
+
+/// Wraps a connection that did not come from a pool, such as the one given to
+/// `LibsqlConnectionManager::init_step`, for use with the helpers in `query`.
+/// It runs no query hooks.
+impl From<Connection> for LibsqlConnection {
+    fn from(conn: Connection) -> Self {
+        Self::new(conn, None, None, None, None, None)
+    }
+}
+
+impl Deref for LibsqlConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl DerefMut for LibsqlConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn pings_during(conn: &LibsqlConnection, wait: Duration) -> u64 {
+        let pings = &conn._keepalive.as_ref().unwrap().pings;
+        let before = pings.load(Ordering::Relaxed);
+        tokio::time::sleep(wait).await;
+        pings.load(Ordering::Relaxed) - before
+    }
+
+    #[tokio::test]
+    async fn keepalive_only_pings_idle_connections() {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let interval = Duration::from_millis(5);
+        let conn = LibsqlConnection::new(db.connect().unwrap(), None, None, None, None, Some(interval));
+        let wait = interval * 10;
+
+        // Not yet returned to a pool.
+        assert_eq!(pings_during(&conn, wait).await, 0);
+
+        conn.set_idle();
+        assert!(pings_during(&conn, wait).await > 0);
+
+        conn.set_checked_out().await;
+        assert_eq!(pings_during(&conn, wait).await, 0);
+    }
+
+    #[cfg(feature = "deadpool")]
+    #[tokio::test]
+    async fn keepalive_pings_quiet_connections_when_idle_when_quiet() {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let interval = Duration::from_millis(20);
+        let conn = LibsqlConnection::new(db.connect().unwrap(), None, None, None, None, Some(interval));
+        conn.set_idle_when_quiet();
+        assert!(pings_during(&conn, interval * 5).await > 0);
+
+        // Statements more often than the interval keep it from pinging.
+        let pings = &conn._keepalive.as_ref().unwrap().pings;
+        let before = pings.load(Ordering::Relaxed);
+        for _ in 0..10 {
+            conn.execute_batch("SELECT 1;").await.unwrap();
+            tokio::time::sleep(interval / 4).await;
+        }
+        assert_eq!(pings.load(Ordering::Relaxed) - before, 0);
+    }
+
+    #[tokio::test]
+    async fn keepalive_skips_open_transactions() {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let interval = Duration::from_millis(5);
+        let conn = LibsqlConnection::new(db.connect().unwrap(), None, None, None, None, Some(interval));
+
+        conn.execute_batch("BEGIN;").await.unwrap();
+        conn.set_idle();
+        assert_eq!(pings_during(&conn, interval * 10).await, 0);
+    }
+
+    #[tokio::test]
+    async fn max_concurrent_requests_queues_calls_beyond_the_limit() {
+        use std::sync::atomic::AtomicUsize;
+
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let conn = LibsqlConnection::new(db.connect().unwrap(), None, Some(2), None, None, None);
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+        // Stands in for a remote request that takes a while to answer.
+        let request = || conn.run("SELECT 1;", async {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        });
+        futures::future::try_join_all((0..6).map(|_| request())).await.unwrap();
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
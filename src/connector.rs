@@ -0,0 +1,67 @@
+//! Type-erased HTTP connectors for `LibsqlConnectionManager::connector`.
+
+use std::error::Error;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{poll_fn, FutureExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::BoxFuture;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+type Connect = Arc<dyn Fn(http::Uri) -> BoxFuture<Result<Box<dyn Socket>, BoxError>> + Send + Sync>;
+
+/// A socket returned by a connector, with the bounds libsql needs.
+pub(crate) trait Socket: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T> Socket for T where T: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl hyper::client::connect::Connection for Box<dyn Socket> {
+    fn connected(&self) -> hyper::client::connect::Connected {
+        self.as_ref().connected()
+    }
+}
+
+/// A connector shared by every `Database` a manager builds. Each connect
+/// runs on a clone of the wrapped connector, so clones share whatever the
+/// connector shares, such as a TLS session cache.
+#[derive(Clone)]
+pub(crate) struct SharedConnector {
+    connect: Connect,
+}
+
+impl SharedConnector {
+    pub(crate) fn new<C>(connector: C) -> Self
+    where
+        C: tower::Service<http::Uri> + Send + Sync + Clone + 'static,
+        C::Response: Socket,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        let connect = move |uri| {
+            let mut connector = connector.clone();
+            async move {
+                poll_fn(|cx| connector.poll_ready(cx)).await.map_err(Into::into)?;
+                let socket = connector.call(uri).await.map_err(Into::into)?;
+                Ok(Box::new(socket) as Box<dyn Socket>)
+            }.boxed()
+        };
+        Self { connect: Arc::new(connect) }
+    }
+}
+
+impl tower::Service<http::Uri> for SharedConnector {
+    type Response = Box<dyn Socket>;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: http::Uri) -> Self::Future {
+        (self.connect)(uri)
+    }
+}
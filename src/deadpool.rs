@@ -0,0 +1,42 @@
+//! `deadpool` support for `LibsqlConnectionManager`.
+//!
+//! The same manager implements `deadpool::managed::Manager`, reusing the
+//! `connect`/`is_valid` logic of its `bb8::ManageConnection` impl.
+//!
+//! deadpool recycles a connection as it is checked out again, and does not
+//! report when it is returned. A connection returned inside a transaction is
+//! therefore dropped at its next checkout rather than at its return, and a
+//! `keepalive_interval` ping runs once no statement has run on a connection
+//! for an interval instead of as soon as it is returned.
+
+use bb8::ManageConnection;
+use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+
+use crate::connection::LibsqlConnection;
+use crate::errors::ConnectionManagerError;
+use crate::LibsqlConnectionManager;
+
+/// A `deadpool` pool of libsql connections.
+pub type Pool = managed::Pool<LibsqlConnectionManager>;
+
+impl managed::Manager for LibsqlConnectionManager {
+    type Type = LibsqlConnection;
+    type Error = ConnectionManagerError;
+
+    async fn create(&self) -> Result<LibsqlConnection, ConnectionManagerError> {
+        let conn = self.connect().await?;
+        conn.set_idle_when_quiet();
+        Ok(conn)
+    }
+
+    async fn recycle(&self, conn: &mut LibsqlConnection, _: &Metrics) -> RecycleResult<ConnectionManagerError> {
+        // The previous borrower never committed or rolled back; dropping the
+        // connection rolls the transaction back, like `has_broken` for bb8.
+        if !conn.is_autocommit() {
+            self.report_open_transaction();
+            return Err(RecycleError::message("connection was returned with an open transaction"));
+        }
+        self.is_valid(conn).await?;
+        Ok(())
+    }
+}
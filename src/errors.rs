@@ -1,11 +1,170 @@
-use std::{fmt, error};
+use std::{fmt, error, io};
 
 use std::sync::mpsc::RecvError;
 
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_NOTFOUND: i32 = 12;
+const SQLITE_FULL: i32 = 13;
+const SQLITE_NOTADB: i32 = 26;
+
 #[derive(Debug)]
 pub enum ConnectionManagerError {
     LibsqlError(libsql::Error),
     RecvError(RecvError),
+    IoError(io::Error),
+    Timeout,
+    /// The manager's configuration was rejected, e.g. by
+    /// `LibsqlConnectionManager::build`.
+    Config(String),
+    /// The manager was shut down with `LibsqlConnectionManager::close`.
+    Closed,
+    /// The primary's replication log no longer matches the local replica,
+    /// e.g. because the remote database was reset or restored. The replica
+    /// files must be deleted so the replica can be cloned afresh.
+    GenerationMismatch,
+    /// libsql panicked while loading an extension; only reported with the
+    /// `extension_panic_guard` feature. A panic inside the extension's own
+    /// init function aborts the process instead.
+    ExtensionLoad(String),
+    /// A query returned more rows than `LibsqlConnectionManager::max_rows`.
+    TooManyRows(usize),
+    /// The named `LibsqlConnectionManager::init_step` failed during connect.
+    InitStep(String, Box<ConnectionManagerError>),
+    /// The query was cancelled, see `query::query_cancellable`.
+    Cancelled,
+    /// `query::query_scalar` read a NULL into the named non-optional type.
+    NullValue(String),
+    /// The pool is drained for maintenance, see `pool::DrainablePool::drain`.
+    Draining,
+    /// A statement was bound to more parameters (the first count) than
+    /// `LibsqlConnectionManager::max_parameters` (the second).
+    TooManyParameters(usize, usize),
+}
+
+impl ConnectionManagerError {
+    /// Returns the wrapped `libsql::Error` for the `LibsqlError` variant,
+    /// looking through `InitStep`.
+    pub fn as_libsql(&self) -> Option<&libsql::Error> {
+        match self {
+            Self::LibsqlError(err) => Some(err),
+            Self::InitStep(_, err) => err.as_libsql(),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the database was busy or locked by another connection.
+    pub fn is_busy(&self) -> bool {
+        matches!(self.sqlite_code(), Some(SQLITE_BUSY) | Some(SQLITE_LOCKED))
+            || self.message_contains(&["database is locked", "database table is locked"])
+    }
+
+    /// Returns `true` if the server rejected the credentials: an HTTP 401 or
+    /// 403, a gRPC `Unauthenticated` or `PermissionDenied` status from
+    /// replication, or an "unauthorized" answer from the server. SQLite's own
+    /// `SQLITE_AUTH` and `SQLITE_PERM` deny a statement or a file, not the
+    /// credentials, and do not count.
+    pub fn is_unauthorized(&self) -> bool {
+        self.is_remote()
+            && (matches!(self.http_status(), Some(401) | Some(403))
+                || self.grpc_code_is(&["Unauthenticated", "PermissionDenied"])
+                || self.message_contains(&["unauthorized", "unauthenticated"]))
+    }
+
+    /// Returns `true` if the database file is corrupt or not a database.
+    pub fn is_corrupt(&self) -> bool {
+        matches!(self.sqlite_code(), Some(SQLITE_CORRUPT) | Some(SQLITE_NOTADB))
+            || self.message_contains(&["database disk image is malformed", "file is not a database"])
+    }
+
+    /// Returns `true` if a write failed because the disk (or the database's
+    /// `max_page_count`) is full, so the app can stop accepting writes.
+    pub fn is_disk_full(&self) -> bool {
+        self.sqlite_code() == Some(SQLITE_FULL)
+            || self.message_contains(&["database or disk is full"])
+    }
+
+    /// Returns `true` if the requested row, database or namespace does not
+    /// exist. A missing table or column is a SQL error and does not count.
+    pub fn is_not_found(&self) -> bool {
+        match self.as_libsql() {
+            Some(libsql::Error::QueryReturnedNoRows) => true,
+            Some(libsql::Error::SqliteFailure(..)) => self.sqlite_code() == Some(SQLITE_NOTFOUND),
+            _ if self.is_remote() => {
+                self.http_status() == Some(404)
+                    || self.grpc_code_is(&["NotFound"])
+                    || self.message_contains(&["requested namespace doesn't exist"])
+                    || (self.message_contains(&["namespace `"]) && self.message_contains(&["` doesn't exist", "` not found"]))
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the error came from the transport to the remote rather
+    /// than from the database itself.
+    pub fn is_network(&self) -> bool {
+        match self.as_libsql() {
+            // libsql also reports local databases it cannot open this way.
+            Some(libsql::Error::ConnectionFailed(_)) => !self.message_contains(&["local database"]),
+            _ if self.is_remote() => {
+                matches!(self.http_status(), Some(502) | Some(503) | Some(504))
+                    || self.grpc_code_is(&["Unavailable"])
+                    || self.message_contains(&[
+                        "http error:",
+                        "stream closed:",
+                        "transport error",
+                        "error trying to connect",
+                        "connection refused",
+                        "connection reset",
+                        "connection closed before message completed",
+                        "dns error",
+                    ])
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the error came from talking to a server, over Hrana or the
+    /// replication protocol.
+    fn is_remote(&self) -> bool {
+        matches!(self.as_libsql(), Some(libsql::Error::Hrana(_)) | Some(libsql::Error::Replication(_)))
+    }
+
+    /// The HTTP status tonic maps to a gRPC status when the server answers
+    /// replication requests with a plain HTTP error.
+    fn http_status(&self) -> Option<u16> {
+        const PREFIX: &str = "mapped from http status code ";
+        let message = self.as_libsql()?.to_string().to_lowercase();
+        let start = message.find(PREFIX)? + PREFIX.len();
+        message[start..].get(..3)?.parse().ok()
+    }
+
+    /// Whether the error is a gRPC status with one of `codes`, as tonic
+    /// formats them, e.g. "status: NotFound, message: ...".
+    fn grpc_code_is(&self, codes: &[&str]) -> bool {
+        let message = match self.as_libsql() {
+            Some(err) => err.to_string(),
+            None => return false,
+        };
+        codes.iter().any(|code| message.contains(&format!("status: {}, ", code)))
+    }
+
+    fn sqlite_code(&self) -> Option<i32> {
+        match self.as_libsql() {
+            Some(libsql::Error::SqliteFailure(code, _))
+            | Some(libsql::Error::RemoteSqliteFailure(code, _, _)) => Some(code & 0xff),
+            _ => None,
+        }
+    }
+
+    fn message_contains(&self, needles: &[&str]) -> bool {
+        let message = match self.as_libsql() {
+            Some(err) => err.to_string().to_lowercase(),
+            None => return false,
+        };
+        needles.iter().any(|needle| message.contains(needle))
+    }
 }
 
 impl fmt::Display for ConnectionManagerError {
@@ -13,6 +172,24 @@ impl fmt::Display for ConnectionManagerError {
         match self {
             ConnectionManagerError::LibsqlError(err) => write!(f, "Libsql Error: `{}`", err),
             ConnectionManagerError::RecvError(err) => write!(f, "Recv Error: `{}`", err),
+            ConnectionManagerError::IoError(err) => write!(f, "Io Error: `{}`", err),
+            ConnectionManagerError::Timeout => write!(f, "Timeout Error: operation timed out"),
+            ConnectionManagerError::Config(message) => write!(f, "Config Error: {}", message),
+            ConnectionManagerError::Closed => write!(f, "Closed Error: connection manager is closed"),
+            ConnectionManagerError::GenerationMismatch => {
+                write!(f, "Generation Mismatch Error: the remote was reset and no longer matches the local replica")
+            },
+            ConnectionManagerError::ExtensionLoad(message) => write!(f, "Extension Load Error: {}", message),
+            ConnectionManagerError::TooManyRows(limit) => write!(f, "Too Many Rows Error: query returned more than {} rows", limit),
+            ConnectionManagerError::InitStep(name, err) => write!(f, "Init Step Error: `{}` failed: {}", name, err),
+            ConnectionManagerError::Cancelled => write!(f, "Cancelled Error: query was cancelled"),
+            ConnectionManagerError::NullValue(ty) => {
+                write!(f, "Null Value Error: query returned NULL, which `{}` cannot hold; use `Option<{}>`", ty, ty)
+            },
+            ConnectionManagerError::Draining => write!(f, "Draining Error: pool is drained for maintenance"),
+            ConnectionManagerError::TooManyParameters(count, limit) => {
+                write!(f, "Too Many Parameters Error: statement binds {} parameters, more than the limit of {}", count, limit)
+            },
         }
     }
 }
@@ -22,13 +199,16 @@ impl error::Error for ConnectionManagerError {
         match self {
             Self::LibsqlError(err) => Some(err),
             Self::RecvError(err) => Some(err),
+            Self::IoError(err) => Some(err),
+            Self::InitStep(_, err) => Some(err.as_ref()),
+            Self::Timeout | Self::Config(_) | Self::Closed | Self::GenerationMismatch | Self::ExtensionLoad(_) | Self::TooManyRows(_) | Self::Cancelled | Self::NullValue(_) | Self::Draining | Self::TooManyParameters(..) => None,
         }
     }
 }
 
 impl From<libsql::Error> for ConnectionManagerError {
     fn from(value: libsql::Error) -> Self {
-        ConnectionManagerError::LibsqlError(value) 
+        ConnectionManagerError::LibsqlError(value)
     }
 }
 
@@ -37,3 +217,35 @@ impl From<RecvError> for ConnectionManagerError {
         ConnectionManagerError::RecvError(value) 
     }
 }
+
+impl From<io::Error> for ConnectionManagerError {
+    fn from(value: io::Error) -> Self {
+        ConnectionManagerError::IoError(value)
+    }
+}
+
+impl From<bb8::RunError<ConnectionManagerError>> for ConnectionManagerError {
+    fn from(value: bb8::RunError<ConnectionManagerError>) -> Self {
+        match value {
+            bb8::RunError::User(err) => err,
+            bb8::RunError::TimedOut => ConnectionManagerError::Timeout,
+        }
+    }
+}
+
+/// A `bb8::ErrorSink` that logs `ConnectionManagerError`s through `tracing`.
+/// Only the error's `Display` form is logged, which never includes auth tokens.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingErrorSink;
+
+#[cfg(feature = "tracing")]
+impl bb8::ErrorSink<ConnectionManagerError> for TracingErrorSink {
+    fn sink(&self, error: ConnectionManagerError) {
+        tracing::error!(error = %error, "libsql connection error");
+    }
+
+    fn boxed_clone(&self) -> Box<dyn bb8::ErrorSink<ConnectionManagerError>> {
+        Box::new(*self)
+    }
+}
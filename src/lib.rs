@@ -10,7 +10,7 @@
 //!
 //! ```rust,no_run
 //! use std::{env, error::Error};
-//! use r2d2_libsql::LibsqlConnectionManager;
+//! use bb8_libsql::LibsqlConnectionManager;
 //!  
 //! use dotenvy::dotenv;
 //!  
@@ -42,112 +42,2052 @@
 //! ```
 pub use libsql;
 use async_trait::async_trait;
-use libsql::Connection;
+use libsql::{replication::Replicated, Connection, Database, OpenFlags};
+use std::collections::hash_map::RandomState;
 use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell};
 
+use connection::{LibsqlConnection, QueryLogger, RowLimit};
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod connection;
+#[cfg(feature = "connector")]
+mod connector;
+#[cfg(feature = "deadpool")]
+pub mod deadpool;
 pub mod errors;
+mod macros;
+pub mod pool;
+pub mod query;
+pub mod row;
+pub mod schema;
+pub mod value;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 enum Source {
     Local(PathBuf),
-    Remote(String, String),
+    Remote(String, AuthToken),
     LocalReplica(PathBuf),
-    RemoteReplica(PathBuf, String, String, Duration),
+    RemoteReplica(PathBuf, String, AuthToken, Duration),
+}
+
+#[derive(Debug, Clone, Hash)]
+enum AuthToken {
+    Static(String),
+    File(PathBuf),
+}
+
+impl AuthToken {
+    async fn resolve(&self) -> Result<String, errors::ConnectionManagerError> {
+        match self {
+            AuthToken::Static(token) => Ok(token.clone()),
+            AuthToken::File(path) => Ok(tokio::fs::read_to_string(path).await?.trim().to_string()),
+        }
+    }
+}
+
+/// Flags selecting the operations run by `LibsqlConnectionManager::maintenance`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaintenanceOps {
+    /// Rebuilds the database file with `VACUUM`.
+    pub vacuum: bool,
+    /// Refreshes query planner statistics with `ANALYZE`.
+    pub analyze: bool,
+    /// Runs a full WAL checkpoint with `PRAGMA wal_checkpoint(FULL)`.
+    pub wal_checkpoint: bool,
+    /// Runs `PRAGMA optimize`.
+    pub optimize: bool,
+}
+
+/// SQLite locking modes, see `LibsqlConnectionManager::locking_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockingMode {
+    Normal,
+    Exclusive,
+}
+
+impl LockingMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            LockingMode::Normal => "NORMAL",
+            LockingMode::Exclusive => "EXCLUSIVE",
+        }
+    }
+}
+
+/// `PRAGMA secure_delete` settings, see `LibsqlConnectionManager::secure_delete`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecureDelete {
+    Off,
+    On,
+    /// Zeroes deleted content only where it adds no extra I/O.
+    Fast,
 }
 
+impl SecureDelete {
+    fn as_str(self) -> &'static str {
+        match self {
+            SecureDelete::Off => "OFF",
+            SecureDelete::On => "ON",
+            SecureDelete::Fast => "FAST",
+        }
+    }
+}
+
+/// What a remote replica does when its local files turn out to be corrupt,
+/// see `LibsqlConnectionManager::on_corrupt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CorruptPolicy {
+    /// Fails the build with the corruption error.
+    Fail,
+    /// Deletes the replica files and clones the replica again.
+    DeleteAndResync,
+    /// Renames the replica files with a `.corrupt-<unix time>` suffix and
+    /// clones the replica again.
+    Backup,
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+type SyncProgress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+type SyncFailure = Arc<dyn Fn(u32, &errors::ConnectionManagerError) + Send + Sync>;
+
+type OpenTransaction = Arc<dyn Fn() + Send + Sync>;
+
+type ConnectionHook = Arc<dyn Fn(Connection) -> BoxFuture<Result<(), errors::ConnectionManagerError>> + Send + Sync>;
+
+type TokenProvider = Arc<dyn Fn() -> BoxFuture<Result<String, errors::ConnectionManagerError>> + Send + Sync>;
+
+type UrlResolver = Arc<dyn Fn() -> BoxFuture<Result<String, errors::ConnectionManagerError>> + Send + Sync>;
+
+type RemoteBuild = Arc<dyn Fn(String, String) -> BoxFuture<libsql::Result<Database>> + Send + Sync>;
+
 /// An `bb8::ManageConnection` for `libsql::Connection`s.
-pub struct LibsqlConnectionManager { source: Source }
+///
+/// All connections are opened from one `libsql::Database`, built on the first connect.
+/// Remote replicas are synced once when that `Database` is built.
+/// Cloning is cheap: clones share the configuration and the `Database`. A
+/// builder method called on a clone detaches it, so it builds its own
+/// `Database` for the changed configuration.
+#[derive(Clone)]
+pub struct LibsqlConnectionManager {
+    config: Arc<Config>,
+    state: Arc<State>,
+}
+
+#[derive(Clone)]
+struct Config {
+    source: Source,
+    remote_build: Option<RemoteBuild>,
+    #[cfg(feature = "connector")]
+    connector: Option<connector::SharedConnector>,
+    token_provider: Option<TokenProvider>,
+    url_resolver: Option<UrlResolver>,
+    namespace: Option<String>,
+    on_first_connect: Option<ConnectionHook>,
+    init_steps: Vec<(String, ConnectionHook)>,
+    sync_progress: Option<SyncProgress>,
+    on_sync_failure: Option<SyncFailure>,
+    sync_backoff_cap: Option<Duration>,
+    extensions: Vec<PathBuf>,
+    extensions_dir: Option<PathBuf>,
+    extensions_best_effort: bool,
+    catch_up_then_offline: bool,
+    connection_label: Option<String>,
+    dedup_initial_sync: bool,
+    manual_sync_only: bool,
+    no_wal: bool,
+    locking_mode: Option<LockingMode>,
+    wal_index_in_memory: bool,
+    secure_delete: Option<SecureDelete>,
+    cache_spill: Option<bool>,
+    reset_on_return: bool,
+    checkpoint_on_close: bool,
+    on_corrupt: CorruptPolicy,
+    validation_interval: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    validate_on_create: bool,
+    skip_checks: bool,
+    auto_analyze: Option<Duration>,
+    shared_cache: bool,
+    temp_dir: Option<PathBuf>,
+    query_logger: Option<QueryLogger>,
+    max_concurrent_requests: Option<usize>,
+    max_rows: Option<usize>,
+    truncate_at_max_rows: bool,
+    max_parameters: Option<usize>,
+    on_open_transaction: Option<OpenTransaction>,
+    #[cfg(feature = "otel")]
+    redact_statements: bool,
+    #[cfg(feature = "encryption")]
+    encryption: Option<libsql::EncryptionConfig>,
+}
+
+#[derive(Default)]
+struct State {
+    first_connected: OnceCell<()>,
+    closed: AtomicBool,
+    database: Mutex<Option<Arc<Database>>>,
+    refreshed_token: Mutex<Option<String>>,
+    resolved_url: Mutex<Option<String>>,
+    initial_sync: Mutex<Option<Replicated>>,
+    analyze_task: Mutex<Option<tokio::task::AbortHandle>>,
+}
 
 impl fmt::Debug for LibsqlConnectionManager {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let config = &self.config;
         let mut builder = f.debug_struct("LibsqlConnectionManager");
-        let _ = builder.field("source", &self.source);
+        let _ = builder.field("source", &config.source);
+        let _ = builder.field("remote_build", &config.remote_build.is_some());
+        #[cfg(feature = "connector")]
+        let _ = builder.field("connector", &config.connector.is_some());
+        let _ = builder.field("token_provider", &config.token_provider.is_some());
+        let _ = builder.field("url_resolver", &config.url_resolver.is_some());
+        let _ = builder.field("namespace", &config.namespace);
+        let _ = builder.field("on_first_connect", &config.on_first_connect.is_some());
+        let _ = builder.field("init_steps", &config.init_steps.iter().map(|(name, _)| name).collect::<Vec<_>>());
+        let _ = builder.field("sync_progress", &config.sync_progress.is_some());
+        let _ = builder.field("on_sync_failure", &config.on_sync_failure.is_some());
+        let _ = builder.field("sync_backoff_cap", &config.sync_backoff_cap);
+        let _ = builder.field("extensions", &config.extensions);
+        let _ = builder.field("extensions_dir", &config.extensions_dir);
+        let _ = builder.field("extensions_best_effort", &config.extensions_best_effort);
+        let _ = builder.field("catch_up_then_offline", &config.catch_up_then_offline);
+        let _ = builder.field("connection_label", &config.connection_label);
+        let _ = builder.field("dedup_initial_sync", &config.dedup_initial_sync);
+        let _ = builder.field("manual_sync_only", &config.manual_sync_only);
+        let _ = builder.field("no_wal", &config.no_wal);
+        let _ = builder.field("locking_mode", &config.locking_mode);
+        let _ = builder.field("wal_index_in_memory", &config.wal_index_in_memory);
+        let _ = builder.field("secure_delete", &config.secure_delete);
+        let _ = builder.field("cache_spill", &config.cache_spill);
+        let _ = builder.field("reset_on_return", &config.reset_on_return);
+        let _ = builder.field("checkpoint_on_close", &config.checkpoint_on_close);
+        let _ = builder.field("on_corrupt", &config.on_corrupt);
+        let _ = builder.field("validation_interval", &config.validation_interval);
+        let _ = builder.field("keepalive_interval", &config.keepalive_interval);
+        let _ = builder.field("validate_on_create", &config.validate_on_create);
+        let _ = builder.field("skip_checks", &config.skip_checks);
+        let _ = builder.field("auto_analyze", &config.auto_analyze);
+        let _ = builder.field("shared_cache", &config.shared_cache);
+        let _ = builder.field("temp_dir", &config.temp_dir);
+        let _ = builder.field("query_logger", &config.query_logger.is_some());
+        let _ = builder.field("max_concurrent_requests", &config.max_concurrent_requests);
+        let _ = builder.field("max_rows", &config.max_rows);
+        let _ = builder.field("truncate_at_max_rows", &config.truncate_at_max_rows);
+        let _ = builder.field("max_parameters", &config.max_parameters);
+        let _ = builder.field("on_open_transaction", &config.on_open_transaction.is_some());
+        #[cfg(feature = "otel")]
+        let _ = builder.field("redact_statements", &config.redact_statements);
+        #[cfg(feature = "encryption")]
+        let _ = builder.field("encryption", &config.encryption.is_some());
         builder.finish()
     }
 }
 
 impl LibsqlConnectionManager {
+    fn new(source: Source) -> Self {
+        Self {
+            config: Arc::new(Config {
+                source,
+                remote_build: None,
+                #[cfg(feature = "connector")]
+                connector: None,
+                token_provider: None,
+                url_resolver: None,
+                namespace: None,
+                on_first_connect: None,
+                init_steps: Vec::new(),
+                sync_progress: None,
+                on_sync_failure: None,
+                sync_backoff_cap: None,
+                extensions: Vec::new(),
+                extensions_dir: None,
+                extensions_best_effort: false,
+                catch_up_then_offline: false,
+                connection_label: None,
+                dedup_initial_sync: true,
+                manual_sync_only: false,
+                no_wal: false,
+                locking_mode: None,
+                wal_index_in_memory: false,
+                secure_delete: None,
+                cache_spill: None,
+                reset_on_return: false,
+                checkpoint_on_close: false,
+                on_corrupt: CorruptPolicy::Fail,
+                validation_interval: None,
+                keepalive_interval: None,
+                validate_on_create: false,
+                skip_checks: false,
+                auto_analyze: None,
+                shared_cache: false,
+                temp_dir: None,
+                query_logger: None,
+                max_concurrent_requests: None,
+                max_rows: None,
+                truncate_at_max_rows: false,
+                max_parameters: None,
+                on_open_transaction: None,
+                #[cfg(feature = "otel")]
+                redact_statements: false,
+                #[cfg(feature = "encryption")]
+                encryption: None,
+            }),
+            state: Arc::default(),
+        }
+    }
+
+    /// The configuration, for a builder method to change. The manager gets a
+    /// fresh state, so it does not keep using a `Database` built for the old
+    /// configuration, shared with the clones it was made from.
+    fn config_mut(&mut self) -> &mut Config {
+        self.state = Arc::default();
+        Arc::make_mut(&mut self.config)
+    }
+
     /// Creates a new `LibsqlConnectionManager` from local file.
     /// See `libsql::Builder::new_local`
     pub fn local<P: AsRef<Path>>(path: P) -> Self {
-        Self {
-            source: Source::Local(
-                path.as_ref().to_path_buf()
-            ),
-        }
+        Self::new(Source::Local(
+            path.as_ref().to_path_buf()
+        ))
+    }
+
+    /// Creates a new `LibsqlConnectionManager` for tests: a fresh in-memory
+    /// database shared by every connection of the pool, with `foreign_keys`
+    /// enabled. Each call gets its own database, so tests do not see each
+    /// other's data. The database lives as long as one of its connections, so
+    /// avoid an `idle_timeout` that lets the pool drop all of them.
+    pub fn new_test() -> Self {
+        static NEXT_TEST_DB: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_TEST_DB.fetch_add(1, Ordering::Relaxed);
+        let path = format!("file:bb8-libsql-test-{}-{}?mode=memory&cache=shared", std::process::id(), id);
+        Self::local(path)
+            .shared_cache(true)
+            .init_step("foreign_keys", |conn| async move {
+                conn.execute_batch("PRAGMA foreign_keys = ON;").await?;
+                Ok(())
+            })
     }
 
     /// Creates a new `LibsqlConnectionManager` from remote.
     /// See `libsql::Builder::new_remote`
     pub fn remote(url: &str, token: &str) -> Self {
-        Self {
-            source: Source::Remote(
-                url.to_string(), 
-                token.to_string()
-            ),
-        }
+        Self::new(Source::Remote(
+            url.to_string(),
+            AuthToken::Static(token.to_string())
+        ))
+    }
+
+    /// Creates a new `LibsqlConnectionManager` from remote, reading the auth
+    /// token from `token_path` (e.g. a mounted secret) with surrounding
+    /// whitespace trimmed. The file is read each time the `Database` is built.
+    /// See `libsql::Builder::new_remote`
+    pub fn remote_from_file<P: AsRef<Path>>(url: &str, token_path: P) -> Self {
+        Self::new(Source::Remote(
+            url.to_string(),
+            AuthToken::File(token_path.as_ref().to_path_buf())
+        ))
+    }
+
+    /// Creates a new `LibsqlConnectionManager` from remote whose `Database` is
+    /// built by `f`, for `libsql::Builder` options this crate does not wrap.
+    ///
+    /// `f` is called with the url and token each time the shared `Database` is
+    /// built (again after a failed build), and should start from
+    /// `libsql::Builder::new_remote(url, token)`. libsql does not export the
+    /// builder's type parameter, so `f` builds the `Database` itself.
+    ///
+    /// ```no_run
+    /// # use bb8_libsql::LibsqlConnectionManager;
+    /// let manager = LibsqlConnectionManager::remote_with("libsql://db.example.com", "token", |url, token| async move {
+    ///     libsql::Builder::new_remote(url, token)
+    ///         .build().await
+    /// });
+    /// ```
+    pub fn remote_with<F, Fut>(url: &str, token: &str, f: F) -> Self
+    where
+        F: Fn(String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = libsql::Result<Database>> + Send + 'static,
+    {
+        let mut manager = Self::remote(url, token);
+        manager.config_mut().remote_build = Some(Arc::new(move |url, token| Box::pin(f(url, token))));
+        manager
+    }
+
+    /// Uses `connector` for the HTTP connections of a remote database or
+    /// replica, like `libsql::Builder::connector`. Managers given clones of a
+    /// connector share whatever it shares, e.g. a hyper-rustls connector's TLS
+    /// config and session cache, which saves handshakes for services with
+    /// many pools.
+    ///
+    /// Not supported together with `remote_with`, whose closure can set the
+    /// connector itself.
+    #[cfg(feature = "connector")]
+    pub fn connector<C>(mut self, connector: C) -> Self
+    where
+        C: tower::Service<http::Uri> + Send + Sync + Clone + 'static,
+        C::Response: hyper::client::connect::Connection
+            + tokio::io::AsyncRead
+            + tokio::io::AsyncWrite
+            + Send
+            + Sync
+            + Unpin
+            + 'static,
+        C::Future: Send + 'static,
+        C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.config_mut().connector = Some(connector::SharedConnector::new(connector));
+        self
     }
 
     /// Creates a new `LibsqlConnectionManager` from local replica.
     /// See `libsql::Builder::new_local_replica`
     pub fn local_replica<P: AsRef<Path>>(path: P) -> Self {
-        Self {
-            source: Source::LocalReplica(
-                path.as_ref().to_path_buf(),
-            ),
-        }
+        Self::new(Source::LocalReplica(
+            path.as_ref().to_path_buf(),
+        ))
     }
 
     /// Creates a new `LibsqlConnectionManager` from remote replica.
     /// See `libsql::Builder::new_remote_replica`
     pub fn remote_replica<P: AsRef<Path>>(path: P, url: &str, token: &str, sync_interval: Duration) -> Self {
-        Self {
-            source: Source::RemoteReplica(
-                path.as_ref().to_path_buf(),
-                url.to_string(),
-                token.to_string(),
-                sync_interval
-            ),
+        Self::new(Source::RemoteReplica(
+            path.as_ref().to_path_buf(),
+            url.to_string(),
+            AuthToken::Static(token.to_string()),
+            sync_interval
+        ))
+    }
+
+    /// Creates a new `LibsqlConnectionManager` from remote replica, reading
+    /// the auth token from `token_path` like `remote_from_file`.
+    /// See `libsql::Builder::new_remote_replica`
+    pub fn remote_replica_from_file<P: AsRef<Path>, T: AsRef<Path>>(path: P, url: &str, token_path: T, sync_interval: Duration) -> Self {
+        Self::new(Source::RemoteReplica(
+            path.as_ref().to_path_buf(),
+            url.to_string(),
+            AuthToken::File(token_path.as_ref().to_path_buf()),
+            sync_interval
+        ))
+    }
+
+    /// Creates a new `LibsqlConnectionManager` from remote replica for one
+    /// tenant of a multi-tenant primary: the replica file is `{dir}/{tenant}.db`
+    /// and the libsql namespace is `tenant`. `tenant` may only contain ASCII
+    /// letters, digits, `-` and `_`, which `build()` checks.
+    /// See `libsql::Builder::new_remote_replica`
+    pub fn remote_replica_for_tenant<P: AsRef<Path>>(dir: P, tenant: &str, url: &str, token: &str, sync_interval: Duration) -> Self {
+        let path = dir.as_ref().join(format!("{}.db", tenant));
+        let mut manager = Self::remote_replica(path, url, token, sync_interval);
+        manager.config_mut().namespace = Some(tenant.to_string());
+        manager
+    }
+
+    /// Creates a new `LibsqlConnectionManager` from environment variables:
+    ///
+    /// - `LIBSQL_URL` and `LIBSQL_TOKEN`: a remote database.
+    /// - `LIBSQL_URL`, `LIBSQL_TOKEN`, `LIBSQL_SYNC_PATH` and
+    ///   `LIBSQL_SYNC_INTERVAL` (in seconds): a remote replica stored at
+    ///   `LIBSQL_SYNC_PATH`.
+    /// - `LIBSQL_SYNC_PATH` alone: a local database file.
+    /// - `LIBSQL_EXTENSIONS` (optional): extensions to load, separated like
+    ///   `PATH`.
+    ///
+    /// Fails listing every missing variable. Does not read `.env` files; call
+    /// `dotenvy::dotenv()` first for that.
+    pub fn from_env() -> Result<Self, errors::ConnectionManagerError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let url = var("LIBSQL_URL");
+        let token = var("LIBSQL_TOKEN");
+        let path = var("LIBSQL_SYNC_PATH");
+        let interval = var("LIBSQL_SYNC_INTERVAL");
+
+        let mut missing = Vec::new();
+        if url.is_none() && path.is_none() {
+            missing.push("LIBSQL_URL or LIBSQL_SYNC_PATH");
+        }
+        if url.is_some() && token.is_none() {
+            missing.push("LIBSQL_TOKEN");
+        }
+        if url.is_some() && path.is_some() && interval.is_none() {
+            missing.push("LIBSQL_SYNC_INTERVAL");
+        }
+        if !missing.is_empty() {
+            return Err(config_error(format!("missing environment variables: {}", missing.join(", "))));
         }
+
+        let manager = match (url, token, path, interval) {
+            (Some(url), Some(token), Some(path), Some(interval)) => {
+                let seconds = interval.parse::<u64>().map_err(|_| {
+                    config_error(format!("LIBSQL_SYNC_INTERVAL `{}` is not a number of seconds", interval))
+                })?;
+                Self::remote_replica(path, &url, &token, Duration::from_secs(seconds))
+            },
+            (Some(url), Some(token), None, _) => Self::remote(&url, &token),
+            (None, _, Some(path), _) => Self::local(path),
+            _ => unreachable!("missing variables are reported above"),
+        };
+
+        Ok(match std::env::var_os("LIBSQL_EXTENSIONS") {
+            Some(paths) => manager.extensions(std::env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty())),
+            None => manager,
+        })
     }
-}
 
-#[async_trait]
-impl bb8::ManageConnection for LibsqlConnectionManager {
-    type Connection = Connection;
-    type Error = errors::ConnectionManagerError;
+    /// Runs `f` once, on the first successful connection opened by this manager.
+    /// Useful for one-time setup such as migrations or `VACUUM`. If `f` fails the
+    /// connection is rejected and `f` runs again on the next connect. Connects
+    /// made while `f` runs wait for it, so no connection is handed out before
+    /// the setup is done.
+    /// For work that should run on every checkout see `bb8::CustomizeConnection::on_acquire`.
+    pub fn on_first_connect<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Connection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), errors::ConnectionManagerError>> + Send + 'static,
+    {
+        self.config_mut().on_first_connect = Some(Arc::new(move |conn| Box::pin(f(conn))));
+        self
+    }
 
-    async fn connect(&self) -> Result<Connection, errors::ConnectionManagerError> {
-        Ok(match &self.source {
-            Source::Local(ref path) => {
-                libsql::Builder::new_local(path)
-                    .build().await
-                    .and_then(|builder| builder.connect())
+    /// Fetches a fresh auth token for remote variants once the current one is
+    /// rejected, e.g. after it expires.
+    ///
+    /// If building the shared `Database` fails as unauthorized (see
+    /// `ConnectionManagerError::is_unauthorized`), `f` is called and the build
+    /// is retried once with its token. A remote `Database` is only
+    /// authenticated when queried: a connect failing as unauthorized after the
+    /// build, e.g. in `validate_on_create`, an init step or `on_first_connect`,
+    /// fetches a new token, rebuilds the `Database` and is retried once, and a
+    /// connection failing `is_valid` as unauthorized also fetches a new token
+    /// and drops the shared `Database`, so bb8 replaces the connection from
+    /// one built with the new token.
+    /// Has no effect on local variants.
+    pub fn token_provider<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, errors::ConnectionManagerError>> + Send + 'static,
+    {
+        self.config_mut().token_provider = Some(Arc::new(move || Box::pin(f())));
+        self
+    }
+
+    /// Looks up the url of a remote database with `f` before each connect,
+    /// e.g. from Consul or a Kubernetes service, instead of using the url the
+    /// manager was created with. When `f` returns a url other than the one the
+    /// shared `Database` was built with, the `Database` is rebuilt against it;
+    /// connections already open keep their old endpoint until bb8 replaces
+    /// them. A failing `f` fails the connect. Only supported for remote
+    /// databases.
+    pub fn url_resolver<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, errors::ConnectionManagerError>> + Send + 'static,
+    {
+        self.config_mut().url_resolver = Some(Arc::new(move || Box::pin(f())));
+        self
+    }
+
+    /// Appends a named setup step run on every new connection, after the
+    /// configured pragmas and before `on_first_connect`. Steps run in the order
+    /// they were added; the first failure rejects the connection with
+    /// `ConnectionManagerError::InitStep` carrying the step's name.
+    pub fn init_step<F, Fut>(mut self, name: impl Into<String>, f: F) -> Self
+    where
+        F: Fn(Connection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), errors::ConnectionManagerError>> + Send + 'static,
+    {
+        let step: ConnectionHook = Arc::new(move |conn| Box::pin(f(conn)));
+        self.config_mut().init_steps.push((name.into(), step));
+        self
+    }
+
+    /// Reports progress of remote replica syncs driven by this manager: the
+    /// initial sync when the shared `Database` is built and calls to `sync()`.
+    /// libsql does not report progress while a sync is in flight, so `f` is
+    /// called with `(frames_synced, frame_no)` once each sync completes.
+    /// Has no effect on other variants.
+    pub fn sync_progress<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u64, u64) + Send + Sync + 'static,
+    {
+        self.config_mut().sync_progress = Some(Arc::new(f));
+        self
+    }
+
+    /// Called by the task started with `spawn_sync_task` each time a sync
+    /// fails, with the number of consecutive failures so far. The streak
+    /// resets after a successful sync, so apps can escalate once it passes a
+    /// threshold.
+    pub fn on_sync_failure<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u32, &errors::ConnectionManagerError) + Send + Sync + 'static,
+    {
+        self.config_mut().on_sync_failure = Some(Arc::new(f));
+        self
+    }
+
+    /// Makes the task started with `spawn_sync_task` back off after failed
+    /// syncs: the delay doubles with each consecutive failure, up to `cap`,
+    /// and drops back to the task's interval after a successful sync. Without
+    /// it the task retries at its fixed interval.
+    pub fn sync_backoff_cap(mut self, cap: Duration) -> Self {
+        self.config_mut().sync_backoff_cap = Some(cap);
+        self
+    }
+
+    /// Loads the SQLite extensions at `paths` on every new connection.
+    /// Only supported for variants backed by a local file.
+    pub fn extensions<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.config_mut().extensions.extend(paths.into_iter().map(|path| path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Loads every `.so`, `.dylib` and `.dll` file in `dir` on every new
+    /// connection, in file name order, after those given to `extensions`.
+    /// The directory is listed on each connect, so extensions dropped into it
+    /// are picked up by new connections. Respects `extensions_best_effort`.
+    pub fn extensions_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.config_mut().extensions_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// When `true`, an extension that fails to load is logged and skipped
+    /// instead of failing the whole connection. Defaults to `false`.
+    pub fn extensions_best_effort(mut self, best_effort: bool) -> Self {
+        self.config_mut().extensions_best_effort = best_effort;
+        self
+    }
+
+    /// When `true`, a remote replica syncs once to catch up when its `Database`
+    /// is built and then never syncs in the background, so reads are served
+    /// from the local file even while the remote is unreachable. Use `sync()`
+    /// to catch up again. Writes are still sent to the remote primary.
+    pub fn catch_up_then_offline(mut self, offline: bool) -> Self {
+        self.config_mut().catch_up_then_offline = offline;
+        self
+    }
+
+    /// Labels this manager's connections, e.g. with a tenant id, so they can be
+    /// correlated in traces. With the `tracing` feature the label is recorded
+    /// on the `libsql.connect` span.
+    pub fn connection_label(mut self, label: impl Into<String>) -> Self {
+        self.config_mut().connection_label = Some(label.into());
+        self
+    }
+
+    /// When `true` (the default), a remote replica is synced once when the
+    /// shared `Database` is built and later connections just open the synced
+    /// file. Connects that arrive while that sync is in flight wait for it,
+    /// so every returned connection reflects at least the startup sync.
+    /// When `false`, every new connection syncs before it is returned.
+    pub fn dedup_initial_sync(mut self, dedup: bool) -> Self {
+        self.config_mut().dedup_initial_sync = dedup;
+        self
+    }
+
+    /// Guarantees that a remote replica never syncs on its own: no background
+    /// interval, no sync when the `Database` is built and none on connect.
+    /// The replica only moves forward on explicit `sync()`/`sync_with_timeout()`
+    /// calls, and the `sync_interval` passed to `remote_replica` is ignored.
+    pub fn manual_sync_only(mut self) -> Self {
+        self.config_mut().manual_sync_only = true;
+        self
+    }
+
+    /// Forces a rollback journal (`PRAGMA journal_mode = DELETE`) on a local
+    /// database instead of WAL, for filesystems such as NFS that cannot host
+    /// WAL's shared memory file. Rollback journals serialize readers with the
+    /// writer, so expect lower concurrency. Replicas need WAL and reject this.
+    pub fn no_wal(mut self) -> Self {
+        self.config_mut().no_wal = true;
+        self
+    }
+
+    /// Sets `PRAGMA locking_mode` on every new connection.
+    ///
+    /// `LockingMode::Exclusive` avoids lock churn and can speed up a single
+    /// connection considerably, but the first connection to touch the file
+    /// keeps its lock, so every other pooled connection fails with
+    /// "database is locked". Only use it with `bb8::Builder::max_size(1)`.
+    /// Only supported for local databases.
+    pub fn locking_mode(mut self, mode: LockingMode) -> Self {
+        self.config_mut().locking_mode = Some(mode);
+        self
+    }
+
+    /// When `true`, keeps the WAL index in process memory instead of the
+    /// shared-memory `-shm` file next to the database, for filesystems where
+    /// mapping that file fails or locks up with "database is locked", such as
+    /// some network and container mounts.
+    ///
+    /// libsql has no builder flag for this, so it relies on SQLite keeping the
+    /// index in memory for connections in exclusive locking mode, and needs
+    /// `locking_mode(LockingMode::Exclusive)` to be set as well. Its
+    /// constraints apply: only use it with `bb8::Builder::max_size(1)`, which
+    /// `pool::build_and_warm` checks, and no other process can open the
+    /// database while the connection is open. Only supported for local
+    /// databases. Defaults to `false`.
+    pub fn wal_index_in_memory(mut self, in_memory: bool) -> Self {
+        self.config_mut().wal_index_in_memory = in_memory;
+        self
+    }
+
+    /// Sets `PRAGMA secure_delete` on every new connection, so deleted content
+    /// is overwritten with zeros instead of lingering in free pages.
+    ///
+    /// `SecureDelete::On` costs extra writes for every delete; `Fast` skips
+    /// overwrites that would need extra I/O, so some content can remain in the
+    /// freelist. Only supported for local databases.
+    pub fn secure_delete(mut self, secure_delete: SecureDelete) -> Self {
+        self.config_mut().secure_delete = Some(secure_delete);
+        self
+    }
+
+    /// Sets `PRAGMA cache_spill` on every new connection. With `false`, dirty
+    /// pages stay in the page cache until the transaction commits instead of
+    /// being written to the database file part way, which keeps large
+    /// transactions off the disk until commit.
+    ///
+    /// The cache then grows past `cache_size` for as long as a transaction
+    /// needs, so a big write can use as much memory as the data it changes.
+    /// Only supported for local databases.
+    pub fn cache_spill(mut self, cache_spill: bool) -> Self {
+        self.config_mut().cache_spill = Some(cache_spill);
+        self
+    }
+
+    /// When `true`, a connection is reset before it is handed to the next
+    /// borrower: temp tables and views are dropped and configured pragmas are
+    /// re-applied, so no state leaks between unrelated checkouts. The reset
+    /// runs during `is_valid`, so it requires
+    /// `bb8::Builder::test_on_check_out(true)` (the default). A connection
+    /// returned with an open transaction is dropped when it is returned,
+    /// whether or not this is set, so it never holds locks while idle.
+    pub fn reset_on_return(mut self, reset: bool) -> Self {
+        self.config_mut().reset_on_return = reset;
+        self
+    }
+
+    /// When `true`, `close()` runs `PRAGMA wal_checkpoint(TRUNCATE)`, moving
+    /// the whole WAL into the database file and truncating the `-wal` file,
+    /// so the file can be copied on its own after shutdown. Connections still
+    /// checked out can keep the checkpoint from completing, so drop the pool
+    /// first. Replica connections reject the checkpoint, so for replicas it
+    /// runs on a separate local handle on the replica file. Not supported for
+    /// remote databases, which have no local file.
+    pub fn checkpoint_on_close(mut self, checkpoint: bool) -> Self {
+        self.config_mut().checkpoint_on_close = checkpoint;
+        self
+    }
+
+    /// Sets how a remote replica recovers when building its `Database` fails
+    /// because the local files are corrupt (see
+    /// `ConnectionManagerError::is_corrupt`). With `DeleteAndResync` or
+    /// `Backup` the files are removed or moved aside and the replica is cloned
+    /// from the primary again, once. Defaults to `CorruptPolicy::Fail`; only
+    /// supported for remote replicas.
+    pub fn on_corrupt(mut self, policy: CorruptPolicy) -> Self {
+        self.config_mut().on_corrupt = policy;
+        self
+    }
+
+    /// Runs `SELECT 1` on every remote connection each `interval` while it is
+    /// open, so the server and proxies in between do not drop connections
+    /// that sit idle in the pool. Pick an interval below the shortest idle
+    /// timeout on the path. Pings only run while a connection is idle in a bb8
+    /// pool, from its return until its next checkout, and never inside an
+    /// open transaction, so they cannot interleave with a borrower's
+    /// statements. A connection is only known to be idle once it has been
+    /// returned, and only known to be checked out again with
+    /// `bb8::Builder::test_on_check_out(true)` (the default). deadpool does not
+    /// report returns, so in a deadpool pool a connection counts as idle once
+    /// no statement has run through its wrappers for `interval`, and a
+    /// statement waits for a ping in flight. Only supported for remote
+    /// databases.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.config_mut().keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Skips the `SELECT 1` in `is_valid` for a connection that passed it
+    /// within `interval`, trading a window where a dead connection can be
+    /// handed out for fewer round trips in high-churn pools. `reset_on_return`
+    /// still runs on every checkout.
+    pub fn validation_interval(mut self, interval: Duration) -> Self {
+        self.config_mut().validation_interval = Some(interval);
+        self
+    }
+
+    /// When `true`, every new connection runs the validation query before it
+    /// enters the pool. A remote `Database` only contacts the server when
+    /// queried, so this makes an unreachable host or bad credentials fail at
+    /// `bb8::Builder::build` instead of at the first checkout.
+    /// Defaults to `false`.
+    pub fn validate_on_create(mut self, validate: bool) -> Self {
+        self.config_mut().validate_on_create = validate;
+        self
+    }
+
+    /// When `true`, `connect()` skips `validate_on_create` and the
+    /// per-connection sync of replicas without `dedup_initial_sync`, and
+    /// `is_valid` skips the validation query, so test suites built on this
+    /// crate run quickly. The initial sync of a remote replica still runs, so
+    /// tests see its data. This trades away the detection of broken
+    /// connections and stale replicas, so only set it in tests.
+    /// Defaults to `false`.
+    pub fn skip_checks(mut self, skip: bool) -> Self {
+        self.config_mut().skip_checks = skip;
+        self
+    }
+
+    /// Runs `ANALYZE` on a dedicated connection every `interval`, starting
+    /// one `interval` after the shared `Database` is built, so query planner
+    /// statistics of a long-lived database do not go stale. A run that takes
+    /// longer than `interval` makes the next one skip rather than queue up.
+    /// Failures are logged with the `tracing` feature and the schedule keeps
+    /// going until `close()`. Only supported for local databases.
+    pub fn auto_analyze(mut self, interval: Duration) -> Self {
+        self.config_mut().auto_analyze = Some(interval);
+        self
+    }
+
+    /// Opens a local database with `SQLITE_OPEN_SHAREDCACHE`, so the pool's
+    /// connections share one page cache and use table-level locking.
+    ///
+    /// SQLite discourages shared-cache mode for most applications: WAL gives
+    /// better concurrency, and shared cache turns many contention cases into
+    /// `SQLITE_LOCKED` errors instead of waiting. Only enable it when you need
+    /// its in-process semantics, e.g. a shared in-memory database.
+    pub fn shared_cache(mut self, shared_cache: bool) -> Self {
+        self.config_mut().shared_cache = shared_cache;
+        self
+    }
+
+    /// Sets `PRAGMA temp_store_directory` on every new connection, so temp
+    /// files for large sorts, joins and temp tables are written under `dir`.
+    ///
+    /// The setting is process-wide in SQLite, so it also affects other
+    /// databases opened by the process. Without it SQLite uses `SQLITE_TMPDIR`,
+    /// `TMPDIR` or `/tmp` on Unix, and the user's temp path on Windows.
+    /// Only supported for local databases.
+    pub fn temp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.config_mut().temp_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Calls `f` with the SQL and elapsed time of every `query`, `execute`
+    /// and `execute_batch` run through a pooled `LibsqlConnection`, including
+    /// those of the helpers in `query`, e.g. for slow-query logging. Other
+    /// calls reach the inner `libsql::Connection` and are not logged.
+    pub fn query_logger<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, Duration) + Send + Sync + 'static,
+    {
+        self.config_mut().query_logger = Some(Arc::new(f));
+        self
+    }
+
+    /// Limits how many `query`/`execute` calls a single pooled
+    /// `LibsqlConnection` runs at once; further calls on it wait for a slot.
+    ///
+    /// A remote connection multiplexes concurrent calls made through a shared
+    /// reference, so without a limit one connection can burst any number of
+    /// requests at the server. The total in flight is at most the pool's
+    /// `max_size` times `limit`. A call holds its slot until it returns, not
+    /// while its `Rows` are consumed.
+    pub fn max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.config_mut().max_concurrent_requests = Some(limit);
+        self
+    }
+
+    /// Caps the rows read by `LibsqlConnection::query_all` and
+    /// `LibsqlConnection::query_stream` and by the helpers in `query` that
+    /// read every row, such as `query::query_as` and `query::query_json`: a
+    /// query returning more than `max_rows` fails with
+    /// `ConnectionManagerError::TooManyRows`, guarding against accidental
+    /// unbounded selects. The `Rows` returned by `LibsqlConnection::query`
+    /// are not limited.
+    pub fn max_rows(mut self, max_rows: usize) -> Self {
+        self.config_mut().max_rows = Some(max_rows);
+        self
+    }
+
+    /// When `true`, queries over `max_rows` return the first `max_rows` rows
+    /// instead of failing. Has no effect without `max_rows`.
+    pub fn truncate_at_max_rows(mut self, truncate: bool) -> Self {
+        self.config_mut().truncate_at_max_rows = truncate;
+        self
+    }
+
+    /// Rejects statements bound to more than `limit` parameters with
+    /// `ConnectionManagerError::TooManyParameters` before they reach SQLite,
+    /// whose own error for this is a terse "too many SQL variables". Set it to
+    /// the `SQLITE_MAX_VARIABLE_NUMBER` of the linked build: 32766 by default
+    /// since SQLite 3.32, 999 before. Applies to `LibsqlConnection::query`
+    /// and `execute`, the helpers in `query` and each row of
+    /// `query::insert_many`; statements run on the underlying
+    /// `libsql::Connection` are not checked.
+    pub fn max_parameters(mut self, limit: usize) -> Self {
+        self.config_mut().max_parameters = Some(limit);
+        self
+    }
+
+    /// Calls `f` when a connection is returned to the pool with an open
+    /// transaction, which means the borrower neither committed nor rolled it
+    /// back. Such connections are always dropped by the pool, rolling the
+    /// transaction back, so this is for reporting the leak.
+    pub fn on_open_transaction<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.config_mut().on_open_transaction = Some(Arc::new(f));
+        self
+    }
+
+    /// When `true`, string and number literals in the `db.statement` of query
+    /// spans are replaced with `?`, so values inlined into SQL do not end up
+    /// in traces. Bound parameters are never recorded. Defaults to `false`.
+    #[cfg(feature = "otel")]
+    pub fn redact_statements(mut self, redact: bool) -> Self {
+        self.config_mut().redact_statements = redact;
+        self
+    }
+
+    /// Encrypts the database file at rest. For a remote replica this applies
+    /// to the local replica file only; it is independent of TLS on the wire.
+    /// Opening an existing unencrypted file (or one encrypted with another key)
+    /// fails with a descriptive error when the `Database` is built.
+    #[cfg(feature = "encryption")]
+    pub fn encryption(mut self, encryption: libsql::EncryptionConfig) -> Self {
+        self.config_mut().encryption = Some(encryption);
+        self
+    }
+
+    /// Syncs a remote replica with its primary.
+    /// See `libsql::Database::sync`
+    pub async fn sync(&self) -> Result<Replicated, errors::ConnectionManagerError> {
+        let db = self.database().await?;
+        let replicated = db.sync().await.map_err(sync_error)?;
+        self.report_sync_progress(&replicated);
+        Ok(replicated)
+    }
+
+    /// Checks that the linked SQLite was compiled with thread safety, which
+    /// pooled connections used from several threads need. A single-thread
+    /// build (`SQLITE_THREADSAFE=0`) fails with a `Config` error instead of
+    /// crashing rarely under load. Call it once at startup.
+    pub fn verify_threadsafe() -> Result<(), errors::ConnectionManagerError> {
+        // SAFETY: `sqlite3_threadsafe` only reads a compile-time constant.
+        let mode = unsafe { libsql::ffi::sqlite3_threadsafe() };
+        if mode == 0 {
+            return Err(config_error("the linked SQLite was compiled without thread safety (SQLITE_THREADSAFE=0)"));
+        }
+        Ok(())
+    }
+
+    /// Builds the shared `Database` of a remote replica now, waiting for its
+    /// initial sync, so a service can delay readiness until the replica is
+    /// usable instead of paying for it on the first checkout. Returns the
+    /// result of the initial sync, reported to `sync_progress` as usual. If
+    /// the `Database` was already built, or `manual_sync_only` skips the
+    /// initial sync, this runs `sync()` instead.
+    pub async fn prewarm_replica(&self) -> Result<Replicated, errors::ConnectionManagerError> {
+        if !matches!(self.config.source, Source::RemoteReplica(..)) {
+            return Err(config_error("prewarm_replica is only supported for remote replicas"));
+        }
+
+        let _ = self.database().await?;
+        let initial_sync = self.state.initial_sync.lock().await.take();
+        match initial_sync {
+            Some(replicated) => Ok(replicated),
+            None => self.sync().await,
+        }
+    }
+
+    /// Spawns a tokio task calling `sync()` every `interval`, starting one
+    /// `interval` from now. Failures are passed to `on_sync_failure` (and
+    /// logged with the `tracing` feature) and the task keeps running, backing
+    /// off if `sync_backoff_cap` is set; abort the returned handle to stop it.
+    /// Pairs with `manual_sync_only` to drive syncs from the app instead of
+    /// libsql's sync interval.
+    pub fn spawn_sync_task(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut next = tokio::time::Instant::now() + interval;
+            let mut failures = 0u32;
+            loop {
+                tokio::time::sleep_until(next).await;
+                let started = tokio::time::Instant::now();
+                let result = manager.sync().await;
+                failures = sync_failures(failures, result.is_ok());
+                if let Err(err) = result {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %err, failures, "libsql background sync failed");
+                    if let Some(on_sync_failure) = &manager.config.on_sync_failure {
+                        on_sync_failure(failures, &err);
+                    }
+                }
+                next = started + sync_delay(interval, manager.config.sync_backoff_cap, failures);
+            }
+        })
+    }
+
+    /// Like `sync()`, but fails with `ConnectionManagerError::Timeout` if the
+    /// sync does not complete within `timeout`.
+    pub async fn sync_with_timeout(&self, timeout: Duration) -> Result<Replicated, errors::ConnectionManagerError> {
+        tokio::time::timeout(timeout, self.sync()).await
+            .map_err(|_| errors::ConnectionManagerError::Timeout)?
+    }
+
+    /// Reports replication progress for a remote replica, for health checks.
+    ///
+    /// libsql does not expose the primary's latest frame number without a
+    /// sync, so the true lag cannot be queried cheaply. Until it does, this
+    /// returns the frame number applied to the local replica, which ops can
+    /// compare against the primary. Returns `None` for other variants or if
+    /// nothing has been replicated yet.
+    pub async fn replica_lag(&self) -> Result<Option<u64>, errors::ConnectionManagerError> {
+        if !matches!(self.config.source, Source::RemoteReplica(..)) {
+            return Ok(None);
+        }
+
+        Ok(self.database().await?.replication_index().await?)
+    }
+
+    /// Shuts the manager down: the shared `Database` is dropped once its open
+    /// connections are, and later connects, including those bb8 makes to
+    /// replenish the pool, fail with `ConnectionManagerError::Closed`.
+    /// Applies to every clone of this manager and cannot be undone.
+    ///
+    /// Only fails if `checkpoint_on_close` is set and the checkpoint fails;
+    /// the manager is closed either way.
+    pub async fn close(&self) -> Result<(), errors::ConnectionManagerError> {
+        let mut database = self.state.database.lock().await;
+        self.state.closed.store(true, Ordering::SeqCst);
+        let db = database.take();
+        drop(database);
+        if let Some(analyze_task) = self.state.analyze_task.lock().await.take() {
+            analyze_task.abort();
+        }
+
+        let db = match db {
+            Some(db) if self.config.checkpoint_on_close => db,
+            _ => return Ok(()),
+        };
+        let conn = match &self.config.source {
+            // Replica connections reject the checkpoint, so it runs on a plain
+            // local handle on the file once the replica's own is dropped.
+            Source::LocalReplica(path) | Source::RemoteReplica(path, ..) => {
+                drop(db);
+                self.open_replica_file(path).await?
+            },
+            _ => db.connect()?,
+        };
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);").await?;
+        Ok(())
+    }
+
+    /// Runs the selected maintenance operations on a dedicated connection.
+    /// `VACUUM` needs near-exclusive access to the database, so this should be
+    /// called deliberately, outside of the hot path. Not supported for
+    /// replicas, whose connections reject these statements.
+    pub async fn maintenance(&self, ops: MaintenanceOps) -> Result<(), errors::ConnectionManagerError> {
+        if matches!(self.config.source, Source::LocalReplica(..) | Source::RemoteReplica(..)) {
+            return Err(config_error("maintenance is not supported for replicas"));
+        }
+        let conn = bb8::ManageConnection::connect(self).await?;
+
+        if ops.vacuum {
+            conn.execute_batch("VACUUM;").await?;
+        }
+        if ops.analyze {
+            conn.execute_batch("ANALYZE;").await?;
+        }
+        if ops.optimize {
+            conn.execute_batch("PRAGMA optimize;").await?;
+        }
+        if ops.wal_checkpoint {
+            conn.execute_batch("PRAGMA wal_checkpoint(FULL);").await?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `VACUUM INTO` on a dedicated connection, writing a defragmented
+    /// copy of the database to `path`. Unlike `backup_to` this never
+    /// overwrites: it fails if `path` already exists. Only supported for
+    /// local databases: replica connections reject `VACUUM INTO`.
+    pub async fn snapshot_into<P: AsRef<Path>>(&self, path: P) -> Result<(), errors::ConnectionManagerError> {
+        self.ensure_local("snapshot_into")?;
+        let path = path.as_ref();
+        if tokio::fs::metadata(path).await.is_ok() {
+            let message = format!("snapshot destination `{}` already exists", path.display());
+            return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, message).into());
+        }
+        self.vacuum_into(path).await
+    }
+
+    /// Writes a consistent copy of the database to `dest` while the pool keeps
+    /// serving, replacing `dest` if it exists. libsql does not expose SQLite's
+    /// online backup API, so the copy is made with `VACUUM INTO` a temporary
+    /// file next to `dest`, which is then renamed over it. Replica connections
+    /// reject `VACUUM INTO`, so a replica is copied through a separate local
+    /// handle on its file, as of its last sync. Not supported for remote
+    /// databases, which have no local file.
+    pub async fn backup_to<P: AsRef<Path>>(&self, dest: P) -> Result<(), errors::ConnectionManagerError> {
+        if matches!(self.config.source, Source::Remote(..)) {
+            return Err(config_error("backup_to is not supported for remote databases"));
+        }
+        let dest = dest.as_ref();
+        let mut tmp = dest.as_os_str().to_owned();
+        tmp.push(".backup-tmp");
+        let tmp = PathBuf::from(tmp);
+        if let Err(err) = tokio::fs::remove_file(&tmp).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+
+        self.vacuum_into(&tmp).await?;
+        tokio::fs::rename(&tmp, dest).await?;
+        Ok(())
+    }
+
+    /// Validates the configuration and returns the manager, so misconfiguration
+    /// fails at startup rather than on the first `connect()`.
+    pub fn build(self) -> Result<Self, errors::ConnectionManagerError> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Returns a hash of the configuration, so frameworks can key a cache of
+    /// pools by it instead of opening duplicate pools for the same database.
+    ///
+    /// Covers the database (variant, path, url, sync interval) and every
+    /// option, but only whether a hook such as `query_logger` is set, not
+    /// which one. Tokens are hashed with the rest under a key chosen at random
+    /// per process, so the fingerprint cannot be used to test guesses of a
+    /// token and is only comparable within one process.
+    pub fn config_fingerprint(&self) -> u64 {
+        static KEYS: OnceLock<RandomState> = OnceLock::new();
+        let config = &self.config;
+        let mut hasher = KEYS.get_or_init(RandomState::new).build_hasher();
+        config.source.hash(&mut hasher);
+        config.remote_build.is_some().hash(&mut hasher);
+        #[cfg(feature = "connector")]
+        config.connector.is_some().hash(&mut hasher);
+        config.token_provider.is_some().hash(&mut hasher);
+        config.url_resolver.is_some().hash(&mut hasher);
+        config.namespace.hash(&mut hasher);
+        config.on_first_connect.is_some().hash(&mut hasher);
+        config.init_steps.iter().map(|(name, _)| name).for_each(|name| name.hash(&mut hasher));
+        config.sync_progress.is_some().hash(&mut hasher);
+        config.on_sync_failure.is_some().hash(&mut hasher);
+        config.sync_backoff_cap.hash(&mut hasher);
+        config.extensions.hash(&mut hasher);
+        config.extensions_dir.hash(&mut hasher);
+        config.extensions_best_effort.hash(&mut hasher);
+        config.catch_up_then_offline.hash(&mut hasher);
+        config.connection_label.hash(&mut hasher);
+        config.dedup_initial_sync.hash(&mut hasher);
+        config.manual_sync_only.hash(&mut hasher);
+        config.no_wal.hash(&mut hasher);
+        config.locking_mode.hash(&mut hasher);
+        config.wal_index_in_memory.hash(&mut hasher);
+        config.secure_delete.hash(&mut hasher);
+        config.cache_spill.hash(&mut hasher);
+        config.reset_on_return.hash(&mut hasher);
+        config.checkpoint_on_close.hash(&mut hasher);
+        config.on_corrupt.hash(&mut hasher);
+        config.validation_interval.hash(&mut hasher);
+        config.keepalive_interval.hash(&mut hasher);
+        config.validate_on_create.hash(&mut hasher);
+        config.skip_checks.hash(&mut hasher);
+        config.auto_analyze.hash(&mut hasher);
+        config.shared_cache.hash(&mut hasher);
+        config.temp_dir.hash(&mut hasher);
+        config.query_logger.is_some().hash(&mut hasher);
+        config.max_concurrent_requests.hash(&mut hasher);
+        config.max_rows.hash(&mut hasher);
+        config.truncate_at_max_rows.hash(&mut hasher);
+        config.max_parameters.hash(&mut hasher);
+        config.on_open_transaction.is_some().hash(&mut hasher);
+        #[cfg(feature = "otel")]
+        config.redact_statements.hash(&mut hasher);
+        #[cfg(feature = "encryption")]
+        config.encryption.is_some().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Checks the configuration without opening any connection, so a service's
+    /// database config can be validated offline (e.g. behind a `--check` flag).
+    /// Unlike a ping, this never touches the network.
+    pub async fn check_config(&self) -> Result<(), errors::ConnectionManagerError> {
+        self.validate()?;
+
+        match &self.config.source {
+            Source::Local(path) | Source::LocalReplica(path) | Source::RemoteReplica(path, ..) => {
+                if let Ok(metadata) = tokio::fs::metadata(path).await {
+                    if metadata.is_dir() {
+                        return Err(config_error(format!("database path `{}` is a directory", path.display())));
+                    }
+                }
+            },
+            Source::Remote(..) => {},
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), errors::ConnectionManagerError> {
+        match &self.config.source {
+            Source::Local(path) | Source::LocalReplica(path) => {
+                validate_path(path)?;
             },
             Source::Remote(url, token) => {
-                libsql::Builder::new_remote(url.to_string(), token.to_string())
-                    .build().await
-                    .and_then(|builder| builder.connect())
+                validate_url(url)?;
+                validate_token(token)?;
+            },
+            Source::RemoteReplica(path, url, token, sync_interval) => {
+                validate_path(path)?;
+                validate_url(url)?;
+                validate_token(token)?;
+                if sync_interval.is_zero() {
+                    return Err(config_error("sync_interval must be non-zero"));
+                }
+            },
+        }
+
+        if let Some(interval) = self.config.keepalive_interval {
+            if !matches!(self.config.source, Source::Remote(..)) {
+                return Err(config_error("keepalive_interval is only supported for remote databases"));
+            }
+            if interval.is_zero() {
+                return Err(config_error("keepalive_interval must be non-zero"));
+            }
+        }
+        if self.config.url_resolver.is_some() && !matches!(self.config.source, Source::Remote(..)) {
+            return Err(config_error("url_resolver is only supported for remote databases"));
+        }
+        if let Some(interval) = self.config.auto_analyze {
+            if !matches!(self.config.source, Source::Local(..)) {
+                return Err(config_error("auto_analyze is only supported for local databases"));
+            }
+            if interval.is_zero() {
+                return Err(config_error("auto_analyze must be non-zero"));
+            }
+        }
+        if self.config.max_parameters == Some(0) {
+            return Err(config_error("max_parameters must be non-zero"));
+        }
+        if self.config.max_concurrent_requests == Some(0) {
+            return Err(config_error("max_concurrent_requests must be non-zero"));
+        }
+        if let Some(namespace) = &self.config.namespace {
+            if namespace.is_empty() || !namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(config_error(format!("tenant `{}` must be non-empty ASCII letters, digits, `-` or `_`", namespace)));
+            }
+        }
+        #[cfg(feature = "connector")]
+        if self.config.connector.is_some() {
+            if !matches!(self.config.source, Source::Remote(..) | Source::RemoteReplica(..)) {
+                return Err(config_error("connector is only supported for remote databases and replicas"));
+            }
+            if self.config.remote_build.is_some() {
+                return Err(config_error("connector is not supported with remote_with; set it in the closure"));
+            }
+        }
+        if self.config.on_corrupt != CorruptPolicy::Fail && !matches!(self.config.source, Source::RemoteReplica(..)) {
+            return Err(config_error("on_corrupt is only supported for remote replicas"));
+        }
+        if self.config.checkpoint_on_close && matches!(self.config.source, Source::Remote(..)) {
+            return Err(config_error("checkpoint_on_close is not supported for remote databases"));
+        }
+        if self.config.no_wal && !matches!(self.config.source, Source::Local(..)) {
+            return Err(config_error("no_wal is only supported for local databases"));
+        }
+        #[cfg(feature = "encryption")]
+        match &self.config.encryption {
+            Some(_) if !matches!(self.config.source, Source::Local(..) | Source::RemoteReplica(..)) => {
+                return Err(config_error("encryption is only supported for local databases and remote replicas"));
+            },
+            Some(encryption) if encryption.encryption_key.is_empty() => {
+                return Err(config_error("encryption key is empty"));
+            },
+            _ => {},
+        }
+        if self.config.wal_index_in_memory {
+            if !matches!(self.config.source, Source::Local(..)) {
+                return Err(config_error("wal_index_in_memory is only supported for local databases"));
+            }
+            // Exclusive locking breaks every pool larger than one connection,
+            // so it is not turned on behind the caller's back.
+            if self.config.locking_mode != Some(LockingMode::Exclusive) {
+                return Err(config_error("wal_index_in_memory needs locking_mode(LockingMode::Exclusive)"));
+            }
+        }
+        if self.config.shared_cache && !matches!(self.config.source, Source::Local(..)) {
+            return Err(config_error("shared_cache is only supported for local databases"));
+        }
+        // Replica connections reject every pragma that changes a setting as
+        // an unsupported statement.
+        if self.config.locking_mode.is_some() && !matches!(self.config.source, Source::Local(..)) {
+            return Err(config_error("locking_mode is only supported for local databases"));
+        }
+        if self.config.secure_delete.is_some() && !matches!(self.config.source, Source::Local(..)) {
+            return Err(config_error("secure_delete is only supported for local databases"));
+        }
+        if self.config.cache_spill.is_some() && !matches!(self.config.source, Source::Local(..)) {
+            return Err(config_error("cache_spill is only supported for local databases"));
+        }
+        if let Some(dir) = &self.config.temp_dir {
+            if !matches!(self.config.source, Source::Local(..)) {
+                return Err(config_error("temp_dir is only supported for local databases"));
+            }
+            if dir.to_str().is_none() {
+                return Err(config_error(format!("temp_dir `{}` is not valid UTF-8", dir.display())));
+            }
+            if !dir.is_dir() {
+                return Err(config_error(format!("temp_dir `{}` does not exist", dir.display())));
+            }
+        }
+
+        if !self.config.extensions_best_effort {
+            if let Some(path) = self.config.extensions.iter().find(|path| !path.is_file()) {
+                return Err(config_error(format!("extension `{}` does not exist", path.display())));
+            }
+            if let Some(dir) = self.config.extensions_dir.as_ref().filter(|dir| !dir.is_dir()) {
+                return Err(config_error(format!("extensions directory `{}` does not exist", dir.display())));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_extensions(&self, conn: &Connection) -> Result<(), errors::ConnectionManagerError> {
+        let mut paths = self.config.extensions.clone();
+        if let Some(dir) = &self.config.extensions_dir {
+            match extensions_in(dir) {
+                Ok(found) => paths.extend(found),
+                Err(err) if self.config.extensions_best_effort => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(dir = %dir.display(), error = %err, "skipping extensions directory that failed to list");
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                },
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        conn.load_extension_enable()?;
+        for path in &paths {
+            if let Err(err) = load_extension(conn, path) {
+                if !self.config.extensions_best_effort {
+                    let _ = conn.load_extension_disable();
+                    return Err(err);
+                }
+                #[cfg(feature = "tracing")]
+                tracing::warn!(extension = %path.display(), error = %err, "skipping extension that failed to load");
+            }
+        }
+        conn.load_extension_disable()?;
+
+        Ok(())
+    }
+
+    /// Reports a connection returned to the pool with an open transaction,
+    /// which the pool then drops.
+    fn report_open_transaction(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("connection returned to the pool with an open transaction");
+        if let Some(on_open_transaction) = &self.config.on_open_transaction {
+            on_open_transaction();
+        }
+    }
+
+    /// Returns the `Database` shared by every connection of this manager,
+    /// building it on first use.
+    async fn database(&self) -> Result<Arc<Database>, errors::ConnectionManagerError> {
+        // The lock is held across the build and initial sync on purpose, so
+        // concurrent connects queue behind it instead of opening a stale
+        // replica. If the build fails, the next waiter retries it.
+        let url = match &self.config.url_resolver {
+            Some(url_resolver) if matches!(self.config.source, Source::Remote(..)) => Some(url_resolver().await?),
+            _ => None,
+        };
+        let mut database = self.state.database.lock().await;
+        if self.state.closed.load(Ordering::SeqCst) {
+            return Err(errors::ConnectionManagerError::Closed);
+        }
+        let mut resolved_url = self.state.resolved_url.lock().await;
+        if let Some(db) = database.as_ref() {
+            if url.is_none() || *resolved_url == url {
+                return Ok(db.clone());
+            }
+            #[cfg(feature = "tracing")]
+            tracing::info!("remote url changed, rebuilding the database");
+        }
+
+        let db = match self.build_database(url.as_deref()).await {
+            Err(err) if err.is_unauthorized() && self.config.token_provider.is_some() => {
+                self.refresh_token().await?;
+                self.build_database(url.as_deref()).await?
+            },
+            result => result?,
+        };
+        let db = Arc::new(db);
+        *database = Some(db.clone());
+        *resolved_url = url;
+        drop(resolved_url);
+        drop(database);
+
+        if let Some(interval) = self.config.auto_analyze {
+            let mut analyze_task = self.state.analyze_task.lock().await;
+            if analyze_task.is_none() {
+                *analyze_task = Some(self.spawn_analyze_task(interval));
+            }
+        }
+        Ok(db)
+    }
+
+    /// Spawns the `auto_analyze` task. It only holds a weak reference to the
+    /// manager's state, so it stops once every clone of the manager is gone.
+    fn spawn_analyze_task(&self, interval: Duration) -> tokio::task::AbortHandle {
+        let config = self.config.clone();
+        let state = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                let _ = ticks.tick().await;
+                let state = match state.upgrade() {
+                    Some(state) if !state.closed.load(Ordering::SeqCst) => state,
+                    _ => return,
+                };
+                let manager = LibsqlConnectionManager { config: config.clone(), state };
+                let ops = MaintenanceOps { analyze: true, ..MaintenanceOps::default() };
+                if let Err(_err) = manager.maintenance(ops).await {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "libsql scheduled ANALYZE failed");
+                }
+            }
+        }).abort_handle()
+    }
+
+    /// Builds the shared `Database`, against `resolved_url` instead of the
+    /// configured url of a remote database if given.
+    async fn build_database(&self, resolved_url: Option<&str>) -> Result<Database, errors::ConnectionManagerError> {
+        Ok(match &self.config.source {
+            Source::Local(ref path) => {
+                let mut flags = OpenFlags::default();
+                if self.config.shared_cache {
+                    flags |= OpenFlags::from_bits_retain(libsql::ffi::SQLITE_OPEN_SHAREDCACHE as _);
+                }
+
+                let builder = libsql::Builder::new_local(path)
+                    .flags(flags);
+                #[cfg(feature = "encryption")]
+                let builder = match &self.config.encryption {
+                    Some(encryption) => builder.encryption_config(encryption.clone()),
+                    None => builder,
+                };
+                let db = builder.build().await?;
+
+                #[cfg(feature = "encryption")]
+                self.verify_encryption(path, &db).await?;
+
+                db
+            },
+            Source::Remote(url, token) => match &self.config.remote_build {
+                Some(remote_build) => remote_build(resolved_url.unwrap_or(url).to_string(), self.resolve_token(token).await?).await?,
+                None => {
+                    let url = resolved_url.unwrap_or(url);
+                    let builder = libsql::Builder::new_remote(url.to_string(), self.resolve_token(token).await?);
+                    #[cfg(feature = "connector")]
+                    let builder = match &self.config.connector {
+                        Some(connector) => builder.connector(connector.clone()),
+                        None => builder,
+                    };
+                    builder.build().await?
+                },
             },
             Source::LocalReplica(path) => {
                 libsql::Builder::new_local_replica(path)
-                    .build().await
-                    .and_then(|builder| builder.connect())
+                    .build().await?
             },
             Source::RemoteReplica(path, url, token, sync_interval) => {
-                libsql::Builder::new_remote_replica(path, url.to_string(), token.to_string())
-                    .sync_interval(sync_interval.clone())
-                    .build().await
-                    .and_then(|builder| builder.connect())
+                match self.build_remote_replica(path, url, token, *sync_interval).await {
+                    Err(err) if err.is_corrupt() && self.config.on_corrupt != CorruptPolicy::Fail => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(path = %path.display(), error = %err, "replica is corrupt, cloning it again");
+                        self.discard_replica(path).await?;
+                        self.build_remote_replica(path, url, token, *sync_interval).await?
+                    },
+                    result => result?,
+                }
             },
-        }?)
+        })
+    }
+
+    fn wrap_connection(&self, conn: Connection) -> LibsqlConnection {
+        let mut conn = LibsqlConnection::new(
+            conn,
+            self.config.query_logger.clone(),
+            self.config.max_concurrent_requests,
+            self.config.max_rows.map(|max_rows| RowLimit { max_rows, truncate: self.config.truncate_at_max_rows }),
+            self.config.max_parameters,
+            self.config.keepalive_interval,
+        );
+        conn.replica = matches!(self.config.source, Source::LocalReplica(..) | Source::RemoteReplica(..));
+
+        #[cfg(feature = "otel")]
+        let conn = conn.with_spans(connection::QuerySpans {
+            variant: match self.config.source {
+                Source::Local(..) => "local",
+                Source::Remote(..) => "remote",
+                Source::LocalReplica(..) => "local_replica",
+                Source::RemoteReplica(..) => "remote_replica",
+            },
+            redact: self.config.redact_statements,
+        });
+
+        conn
+    }
+
+    /// Fails with a `Config` error naming `operation` unless this manager is
+    /// for a local database.
+    fn ensure_local(&self, operation: &str) -> Result<(), errors::ConnectionManagerError> {
+        match self.config.source {
+            Source::Local(..) => Ok(()),
+            _ => Err(config_error(format!("{} is only supported for local databases", operation))),
+        }
+    }
+
+    async fn vacuum_into(&self, dest: &Path) -> Result<(), errors::ConnectionManagerError> {
+        let dest = dest.to_str()
+            .ok_or_else(|| config_error(format!("path `{}` is not valid UTF-8", dest.display())))?;
+
+        let _ = match &self.config.source {
+            Source::LocalReplica(path) | Source::RemoteReplica(path, ..) => {
+                self.open_replica_file(path).await?.execute("VACUUM INTO ?1;", [dest]).await?
+            },
+            _ => bb8::ManageConnection::connect(self).await?.execute("VACUUM INTO ?1;", [dest]).await?,
+        };
+        Ok(())
+    }
+
+    /// Opens the file of a replica as a plain local database, for the
+    /// statements replica connections reject. It reads what the replica has
+    /// synced so far.
+    async fn open_replica_file(&self, path: &Path) -> Result<Connection, errors::ConnectionManagerError> {
+        let builder = libsql::Builder::new_local(path);
+        #[cfg(feature = "encryption")]
+        let builder = match &self.config.encryption {
+            Some(encryption) => builder.encryption_config(encryption.clone()),
+            None => builder,
+        };
+        Ok(builder.build().await?.connect()?)
+    }
+
+    async fn resolve_token(&self, token: &AuthToken) -> Result<String, errors::ConnectionManagerError> {
+        match self.state.refreshed_token.lock().await.clone() {
+            Some(token) => Ok(token),
+            None => token.resolve().await,
+        }
+    }
+
+    async fn refresh_token(&self) -> Result<(), errors::ConnectionManagerError> {
+        if let Some(token_provider) = &self.config.token_provider {
+            let token = token_provider().await?;
+            *self.state.refreshed_token.lock().await = Some(token);
+        }
+        Ok(())
+    }
+
+    async fn apply_pragmas(&self, conn: &Connection) -> Result<(), errors::ConnectionManagerError> {
+        let mut pragmas = String::new();
+        // The locking mode goes first: SQLite only skips the shared-memory
+        // WAL index if exclusive mode is set before the WAL is first used.
+        if let Some(mode) = self.config.locking_mode {
+            pragmas.push_str(&format!("PRAGMA locking_mode = {};", mode.as_str()));
+        }
+        if self.config.no_wal {
+            pragmas.push_str("PRAGMA journal_mode = DELETE;");
+        }
+        if let Some(secure_delete) = self.config.secure_delete {
+            pragmas.push_str(&format!("PRAGMA secure_delete = {};", secure_delete.as_str()));
+        }
+        if let Some(cache_spill) = self.config.cache_spill {
+            pragmas.push_str(&format!("PRAGMA cache_spill = {};", if cache_spill { "ON" } else { "OFF" }));
+        }
+        if let Some(dir) = self.config.temp_dir.as_ref().and_then(|dir| dir.to_str()) {
+            pragmas.push_str(&format!("PRAGMA temp_store_directory = '{}';", dir.replace('\'', "''")));
+        }
+
+        if !pragmas.is_empty() {
+            conn.execute_batch(&pragmas).await?;
+        }
+
+        Ok(())
     }
 
-    async fn is_valid(&self, conn: &mut Connection) -> Result<(), errors::ConnectionManagerError> {
-        Ok(conn.execute_batch("SELECT 1;").await.map(|_| ())?)
+    async fn reset_connection(&self, conn: &Connection) -> Result<(), errors::ConnectionManagerError> {
+        if !conn.is_autocommit() {
+            conn.execute_batch("ROLLBACK;").await?;
+        }
+
+        let mut rows = conn.query("SELECT type, name FROM temp.sqlite_master WHERE type IN ('table', 'view');", ()).await?;
+        let mut drops = String::new();
+        while let Some(row) = rows.next().await? {
+            let kind = if row.get_str(0)? == "view" { "VIEW" } else { "TABLE" };
+            let name = row.get_str(1)?.replace('"', "\"\"");
+            drops.push_str(&format!("DROP {} IF EXISTS temp.\"{}\";", kind, name));
+        }
+        if !drops.is_empty() {
+            conn.execute_batch(&drops).await?;
+        }
+
+        self.apply_pragmas(conn).await
     }
 
-    fn has_broken(&self, _: &mut Connection) -> bool { false }
+    async fn open_connection(&self) -> Result<Connection, errors::ConnectionManagerError> {
+        match self.try_open_connection().await {
+            // A remote `Database` is only authenticated when queried, so an
+            // expired token usually fails the connect after the build.
+            Err(err)
+                if err.is_unauthorized()
+                    && self.config.token_provider.is_some()
+                    && !matches!(self.config.source, Source::Local(..)) =>
+            {
+                self.refresh_token().await?;
+                *self.state.database.lock().await = None;
+                self.try_open_connection().await
+            },
+            result => result,
+        }
+    }
+
+    async fn try_open_connection(&self) -> Result<Connection, errors::ConnectionManagerError> {
+        let db = self.database().await?;
+        // The initial sync result is only for a `prewarm_replica` call that
+        // built the `Database` itself; once a connect has used the replica, a
+        // later `prewarm_replica` must sync again.
+        if matches!(self.config.source, Source::RemoteReplica(..)) {
+            let _ = self.state.initial_sync.lock().await.take();
+        }
+        if !self.config.skip_checks
+            && !self.config.dedup_initial_sync
+            && !self.config.manual_sync_only
+            && matches!(self.config.source, Source::RemoteReplica(..))
+        {
+            let replicated = db.sync().await.map_err(sync_error)?;
+            self.report_sync_progress(&replicated);
+        }
+
+        let conn = db.connect()?;
+        if self.config.validate_on_create && !self.config.skip_checks {
+            conn.execute_batch("SELECT 1;").await?;
+        }
+        self.load_extensions(&conn)?;
+        self.apply_pragmas(&conn).await?;
+
+        for (name, step) in &self.config.init_steps {
+            step(conn.clone()).await
+                .map_err(|err| errors::ConnectionManagerError::InitStep(name.clone(), Box::new(err)))?;
+        }
+
+        if let Some(on_first_connect) = &self.config.on_first_connect {
+            self.state.first_connected.get_or_try_init(|| on_first_connect(conn.clone())).await?;
+        }
+
+        Ok(conn)
+    }
+
+    async fn build_remote_replica(
+        &self,
+        path: &Path,
+        url: &str,
+        token: &AuthToken,
+        sync_interval: Duration,
+    ) -> Result<Database, errors::ConnectionManagerError> {
+        let mut builder = libsql::Builder::new_remote_replica(path, url.to_string(), self.resolve_token(token).await?);
+        if !self.config.catch_up_then_offline && !self.config.manual_sync_only {
+            builder = builder.sync_interval(sync_interval);
+        }
+        #[cfg(feature = "connector")]
+        if let Some(connector) = &self.config.connector {
+            builder = builder.connector(connector.clone());
+        }
+        if let Some(namespace) = &self.config.namespace {
+            builder = builder.namespace(namespace.clone());
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.config.encryption {
+            builder = builder.encryption_config(encryption.clone());
+        }
+        #[cfg(feature = "encryption")]
+        let db = builder.build().await.map_err(|err| encryption_error(path, err))?;
+        #[cfg(not(feature = "encryption"))]
+        let db = builder.build().await?;
+
+        #[cfg(feature = "encryption")]
+        self.verify_encryption(path, &db).await?;
+
+        if !self.config.manual_sync_only {
+            let replicated = db.sync().await.map_err(sync_error)?;
+            self.report_sync_progress(&replicated);
+            *self.state.initial_sync.lock().await = Some(replicated);
+        }
+
+        Ok(db)
+    }
+
+    /// Deletes or moves aside the files of a corrupt replica at `path`,
+    /// according to `on_corrupt`.
+    async fn discard_replica(&self, path: &Path) -> Result<(), errors::ConnectionManagerError> {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        for suffix in ["", "-wal", "-shm", "-client_wal_index"] {
+            let mut file = path.as_os_str().to_owned();
+            file.push(suffix);
+            let result = match self.config.on_corrupt {
+                CorruptPolicy::Backup => {
+                    let mut backup = file.clone();
+                    backup.push(format!(".corrupt-{}", stamp));
+                    tokio::fs::rename(&file, &backup).await
+                },
+                _ => tokio::fs::remove_file(&file).await,
+            };
+            if let Err(err) = result {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    async fn verify_encryption(&self, path: &Path, db: &Database) -> Result<(), errors::ConnectionManagerError> {
+        if self.config.encryption.is_none() {
+            return Ok(());
+        }
+
+        db.connect()?
+            .query("SELECT count(*) FROM sqlite_master;", ()).await
+            .map_err(|err| encryption_error(path, err))?;
+        Ok(())
+    }
+
+    fn report_sync_progress(&self, replicated: &Replicated) {
+        if let Some(sync_progress) = &self.config.sync_progress {
+            sync_progress(replicated.frames_synced() as u64, replicated.frame_no().unwrap_or(0));
+        }
+    }
+}
+
+/// The failure streak after a background sync, reset by a successful one.
+fn sync_failures(failures: u32, synced: bool) -> u32 {
+    if synced {
+        0
+    } else {
+        failures.saturating_add(1)
+    }
+}
+
+/// The delay before the next background sync after `failures` consecutive
+/// failed syncs, see `LibsqlConnectionManager::sync_backoff_cap`.
+fn sync_delay(interval: Duration, cap: Option<Duration>, failures: u32) -> Duration {
+    match cap {
+        Some(cap) if failures > 0 => {
+            let backoff = interval.checked_mul(1 << failures.min(16)).unwrap_or(cap);
+            backoff.min(cap).max(interval)
+        },
+        _ => interval,
+    }
+}
+
+pub(crate) fn config_error(message: impl Into<String>) -> errors::ConnectionManagerError {
+    errors::ConnectionManagerError::Config(message.into())
+}
+
+fn sync_error(err: libsql::Error) -> errors::ConnectionManagerError {
+    // libsql reports a primary whose log id changed since the replica was
+    // cloned as an incompatible log during the sync handshake.
+    if err.to_string().contains("Replica log incompatible") {
+        errors::ConnectionManagerError::GenerationMismatch
+    } else {
+        err.into()
+    }
+}
+
+fn validate_path(path: &Path) -> Result<(), errors::ConnectionManagerError> {
+    if path.as_os_str().is_empty() {
+        return Err(config_error("database path is empty"));
+    }
+    let path_str = match path.to_str() {
+        Some(path_str) => path_str,
+        None => return Err(config_error(format!("database path `{}` is not valid UTF-8", path.display()))),
+    };
+    if let Some((scheme, _)) = path_str.split_once("://") {
+        if REMOTE_SCHEMES.contains(&scheme) {
+            return Err(config_error(format!(
+                "database path `{}` is a remote url; use `LibsqlConnectionManager::remote` or `remote_replica` for it",
+                path_str
+            )));
+        }
+    }
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => {
+            Err(config_error(format!("directory `{}` does not exist", parent.display())))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "extension_panic_guard"))]
+fn load_extension(conn: &Connection, path: &Path) -> Result<(), errors::ConnectionManagerError> {
+    Ok(conn.load_extension(path, None)?)
+}
+
+/// Loads an extension, turning a panic in libsql's Rust code around the load
+/// into an error so it does not unwind through bb8. The extension's own init
+/// function is `extern "C"`, so a panic or crash inside it aborts the process
+/// and cannot be caught here.
+#[cfg(feature = "extension_panic_guard")]
+fn load_extension(conn: &Connection, path: &Path) -> Result<(), errors::ConnectionManagerError> {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    match catch_unwind(AssertUnwindSafe(|| conn.load_extension(path, None))) {
+        Ok(result) => Ok(result?),
+        Err(panic) => {
+            let reason = panic.downcast_ref::<&str>().copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(errors::ConnectionManagerError::ExtensionLoad(format!("loading `{}` panicked: {}", path.display(), reason)))
+        },
+    }
+}
+
+fn extensions_in(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_extension = path.extension()
+            .is_some_and(|ext| ext == "so" || ext == "dylib" || ext == "dll");
+        if is_extension && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+const REMOTE_SCHEMES: &[&str] = &["libsql", "http", "https", "ws", "wss"];
+
+fn validate_url(url: &str) -> Result<(), errors::ConnectionManagerError> {
+    let (scheme, rest) = url.split_once("://")
+        .ok_or_else(|| config_error(format!(
+            "url `{}` has no scheme; use `LibsqlConnectionManager::local` for a database file",
+            url
+        )))?;
+    if scheme == "file" {
+        return Err(config_error(format!(
+            "url `{}` is a local file; use `LibsqlConnectionManager::local` for it",
+            url
+        )));
+    }
+    if !REMOTE_SCHEMES.contains(&scheme) {
+        return Err(config_error(format!("url `{}` has unsupported scheme `{}`", url, scheme)));
+    }
+    if rest.split('/').next().unwrap_or_default().is_empty() {
+        return Err(config_error(format!("url `{}` has no host", url)));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+fn encryption_error(path: &Path, err: libsql::Error) -> errors::ConnectionManagerError {
+    let not_a_database = match &err {
+        libsql::Error::SqliteFailure(code, _) => code & 0xff == 26,
+        _ => err.to_string().contains("file is not a database"),
+    };
+
+    if not_a_database {
+        config_error(format!(
+            "`{}` cannot be opened with the configured encryption key; it may be unencrypted or use another key",
+            path.display()
+        ))
+    } else {
+        err.into()
+    }
+}
+
+fn validate_token(token: &AuthToken) -> Result<(), errors::ConnectionManagerError> {
+    match token {
+        AuthToken::File(path) if !path.is_file() => {
+            Err(config_error(format!("token file `{}` does not exist", path.display())))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl bb8::ManageConnection for LibsqlConnectionManager {
+    type Connection = LibsqlConnection;
+    type Error = errors::ConnectionManagerError;
+
+    async fn connect(&self) -> Result<LibsqlConnection, errors::ConnectionManagerError> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+
+            let span = tracing::debug_span!("libsql.connect", label = tracing::field::Empty);
+            if let Some(label) = &self.config.connection_label {
+                span.record("label", label.as_str());
+            }
+            let conn = self.open_connection().instrument(span).await?;
+            Ok(self.wrap_connection(conn))
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        {
+            let conn = self.open_connection().await?;
+            Ok(self.wrap_connection(conn))
+        }
+    }
+
+    async fn is_valid(&self, conn: &mut LibsqlConnection) -> Result<(), errors::ConnectionManagerError> {
+        conn.set_checked_out().await;
+        if self.config.reset_on_return {
+            self.reset_connection(&conn.conn).await?;
+        }
+        if self.config.skip_checks {
+            return Ok(());
+        }
+        if let (Some(interval), Some(validated)) = (self.config.validation_interval, conn.last_validated) {
+            if validated.elapsed() < interval {
+                return Ok(());
+            }
+        }
+
+        let result = conn.conn.execute_batch("SELECT 1;").await.map(|_| ()).map_err(errors::ConnectionManagerError::from);
+        if let Err(err) = &result {
+            if err.is_unauthorized() && self.config.token_provider.is_some() {
+                self.refresh_token().await?;
+                *self.state.database.lock().await = None;
+            }
+        } else {
+            conn.last_validated = Some(Instant::now());
+        }
+        result
+    }
+
+    fn has_broken(&self, conn: &mut LibsqlConnection) -> bool {
+        // bb8 calls this as a checked-out connection is dropped, so an open
+        // transaction here was never committed or rolled back. Keeping the
+        // connection would hold its locks while it sits idle, so it is
+        // dropped instead, which rolls the transaction back.
+        if conn.is_autocommit() {
+            conn.set_idle();
+            return false;
+        }
+
+        self.report_open_transaction();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_failures_counts_the_streak_and_resets_on_success() {
+        let streak = [false, false, false, true, false]
+            .iter()
+            .scan(0, |failures, synced| {
+                *failures = sync_failures(*failures, *synced);
+                Some(*failures)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(streak, [1, 2, 3, 0, 1]);
+        assert_eq!(sync_failures(u32::MAX, false), u32::MAX);
+    }
+
+    #[test]
+    fn sync_delay_backs_off_up_to_the_cap() {
+        let interval = Duration::from_secs(1);
+        let cap = Some(Duration::from_secs(10));
+        let delays = (0..6).map(|failures| sync_delay(interval, cap, failures).as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(sync_delay(interval, cap, u32::MAX), Duration::from_secs(10));
+        assert_eq!(sync_delay(interval, None, 5), interval);
+        // A cap below the interval never shortens it.
+        assert_eq!(sync_delay(interval, Some(Duration::from_millis(100)), 3), interval);
+    }
+
+    #[cfg(feature = "extension_panic_guard")]
+    #[tokio::test]
+    async fn a_panic_while_loading_an_extension_is_an_error() {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+        conn.load_extension_enable().unwrap();
+
+        // libsql unwraps the path's conversion to a C string, which panics on a NUL byte.
+        let err = load_extension(&conn, Path::new("bogus\0.so")).unwrap_err();
+        assert!(matches!(err, errors::ConnectionManagerError::ExtensionLoad(_)), "{}", err);
+        conn.execute_batch("SELECT 1;").await.unwrap();
+    }
+
+    #[test]
+    fn a_reset_primary_is_reported_as_a_generation_mismatch() {
+        let reset = libsql::Error::Replication("Replica log incompatible with log from primary".into());
+        assert!(matches!(sync_error(reset), errors::ConnectionManagerError::GenerationMismatch));
+
+        let offline = libsql::Error::Replication("connection refused".into());
+        assert!(matches!(sync_error(offline), errors::ConnectionManagerError::LibsqlError(_)));
+    }
+
+    #[tokio::test]
+    async fn is_valid_skips_connections_validated_within_the_interval() {
+        use bb8::ManageConnection;
+
+        let manager = LibsqlConnectionManager::new_test()
+            .validation_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let mut conn = manager.connect().await.unwrap();
+        assert!(conn.last_validated.is_none());
+
+        manager.is_valid(&mut conn).await.unwrap();
+        let validated = conn.last_validated;
+        assert!(validated.is_some());
+        manager.is_valid(&mut conn).await.unwrap();
+        assert_eq!(conn.last_validated, validated);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        manager.is_valid(&mut conn).await.unwrap();
+        assert!(conn.last_validated > validated);
+    }
 }
@@ -0,0 +1,34 @@
+/// Builds query parameters for a pooled connection.
+///
+/// Named parameters use `name => value` pairs, positional parameters are a
+/// plain list. Both map onto libsql's own `named_params!`/`params!`.
+///
+/// ```rust,no_run
+/// use bb8_libsql::{params, LibsqlConnectionManager};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let pool = bb8::Pool::builder()
+///     .build(LibsqlConnectionManager::local("app.db"))
+///     .await?;
+///
+/// let conn = pool.get().await?;
+/// conn.execute("INSERT INTO users (id, name) VALUES (:id, :name)", params! {
+///     ":id" => 5,
+///     ":name" => "alice",
+/// }).await?;
+/// conn.execute("DELETE FROM users WHERE id = ?1", params![5]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! params {
+    () => {
+        ()
+    };
+    ($($name:literal => $value:expr),+ $(,)?) => {
+        $crate::libsql::named_params! { $($name: $value),+ }
+    };
+    ($($value:expr),+ $(,)?) => {
+        $crate::libsql::params![$($value),+]
+    };
+}
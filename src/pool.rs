@@ -0,0 +1,597 @@
+//! Helpers layered over `bb8::Pool<LibsqlConnectionManager>`.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bb8::{Pool, PooledConnection, RunError};
+use libsql::replication::Replicated;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config_error;
+use crate::errors::ConnectionManagerError;
+use crate::query::{self, query_scalar, TransactionBehavior};
+use crate::{LibsqlConnection, LibsqlConnectionManager, Source};
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+const WAIT_READY_MIN_DELAY: Duration = Duration::from_millis(50);
+
+const WAIT_READY_MAX_DELAY: Duration = Duration::from_secs(1);
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const WAIT_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+/// A histogram of how long `pool.get()` waited for a connection.
+#[derive(Debug, Default)]
+pub struct WaitHistogram {
+    buckets: [AtomicU64; WAIT_BUCKETS_MS.len() + 1],
+    count: AtomicU64,
+    total_micros: AtomicU64,
+}
+
+impl WaitHistogram {
+    /// Records a single wait.
+    pub fn record(&self, wait: Duration) {
+        let millis = wait.as_millis();
+        let bucket = WAIT_BUCKETS_MS.iter()
+            .position(|bound| millis <= u128::from(*bound))
+            .unwrap_or(WAIT_BUCKETS_MS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Number of recorded waits.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Sum of all recorded waits.
+    pub fn total(&self) -> Duration {
+        Duration::from_micros(self.total_micros.load(Ordering::Relaxed))
+    }
+
+    /// Returns `(upper_bound, count)` per bucket. The last bucket has no
+    /// upper bound.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        WAIT_BUCKETS_MS.iter()
+            .map(|bound| Some(Duration::from_millis(*bound)))
+            .chain(std::iter::once(None))
+            .zip(self.buckets.iter())
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// A `bb8::Pool` that records how long each `get()` waits for a connection,
+/// so pool starvation shows up when sizing `max_size`.
+#[derive(Debug, Clone)]
+pub struct TimedPool {
+    pool: Pool<LibsqlConnectionManager>,
+    wait_times: Arc<WaitHistogram>,
+}
+
+impl TimedPool {
+    pub fn new(pool: Pool<LibsqlConnectionManager>) -> Self {
+        Self { pool, wait_times: Arc::default() }
+    }
+
+    /// Retrieves a connection like `bb8::Pool::get`, recording the wait.
+    pub async fn get(&self) -> Result<PooledConnection<'_, LibsqlConnectionManager>, RunError<ConnectionManagerError>> {
+        let started = Instant::now();
+        let conn = self.pool.get().await;
+        self.wait_times.record(started.elapsed());
+        conn
+    }
+
+    /// The recorded wait times.
+    pub fn wait_times(&self) -> &WaitHistogram {
+        &self.wait_times
+    }
+
+    /// The wrapped pool.
+    pub fn pool(&self) -> &Pool<LibsqlConnectionManager> {
+        &self.pool
+    }
+}
+
+/// A `bb8::Pool` that can be drained for maintenance that needs the database
+/// to itself, such as an online schema migration.
+///
+/// Only checkouts made through `get` are rejected while draining: connections
+/// taken from `pool()` directly still succeed, and `drain` waits for them to
+/// be returned like any other.
+#[derive(Debug, Clone)]
+pub struct DrainablePool {
+    pool: Pool<LibsqlConnectionManager>,
+    draining: Arc<AtomicBool>,
+    // `get` holds this shared while it checks out, so `drain` can wait for
+    // checkouts that passed the `draining` check before it was set.
+    checkouts: Arc<RwLock<()>>,
+    drains: Arc<Mutex<()>>,
+}
+
+impl DrainablePool {
+    pub fn new(pool: Pool<LibsqlConnectionManager>) -> Self {
+        Self {
+            pool,
+            draining: Arc::default(),
+            checkouts: Arc::default(),
+            drains: Arc::default(),
+        }
+    }
+
+    /// Retrieves a connection like `bb8::Pool::get`, or fails with
+    /// `ConnectionManagerError::Draining` while the pool is drained.
+    pub async fn get(&self) -> Result<PooledConnection<'_, LibsqlConnectionManager>, RunError<ConnectionManagerError>> {
+        let _checkout = self.checkouts.read().await;
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(RunError::User(ConnectionManagerError::Draining));
+        }
+        self.pool.get().await
+    }
+
+    /// Rejects new checkouts, waits for every checked out connection to be
+    /// returned, then runs `f` on a pooled connection no one else is using,
+    /// with the manager's query hooks and limits applied. Checkouts
+    /// resume once `f` completes, fails or is cancelled. Drains run one at a
+    /// time.
+    ///
+    /// There is no bound on the wait, so wrap the call in
+    /// `tokio::time::timeout` if a connection may be held for long.
+    pub async fn drain<F, Fut, T>(&self, f: F) -> Result<T, ConnectionManagerError>
+    where
+        F: FnOnce(LibsqlConnection) -> Fut,
+        Fut: Future<Output = Result<T, ConnectionManagerError>>,
+    {
+        let _drain = self.drains.lock().await;
+        self.draining.store(true, Ordering::SeqCst);
+        let _resume = Resume(&self.draining);
+        drop(self.checkouts.write().await);
+
+        loop {
+            let state = self.pool.state();
+            if state.connections == state.idle_connections {
+                break;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let conn = self.pool.get().await?;
+        f(conn.share()).await
+    }
+
+    /// Returns `true` while `drain` is rejecting checkouts.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// The wrapped pool.
+    pub fn pool(&self) -> &Pool<LibsqlConnectionManager> {
+        &self.pool
+    }
+}
+
+/// Clears a `DrainablePool`'s `draining` flag when dropped.
+struct Resume<'a>(&'a AtomicBool);
+
+impl Drop for Resume<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A pool over a remote replica for reads and a pool over its remote primary
+/// for writes.
+///
+/// Reads are served from the local replica, so they only see a write once
+/// the replica has synced it: on the replica's sync interval, or right away
+/// after `sync`. A connection from `write` reads its own writes from the
+/// primary, so code that must see what it just wrote should read through
+/// `write` or call `sync` first. Transactions only span one side; a
+/// transaction on `write` cannot include reads from `read`.
+#[derive(Debug, Clone)]
+pub struct ReadWriteSplit {
+    replica: LibsqlConnectionManager,
+    read: Pool<LibsqlConnectionManager>,
+    write: Pool<LibsqlConnectionManager>,
+}
+
+impl ReadWriteSplit {
+    /// Builds default pools over `replica`, which must be a remote replica,
+    /// and `primary`, which must be the remote database it replicates.
+    pub async fn new(replica: LibsqlConnectionManager, primary: LibsqlConnectionManager) -> Result<Self, ConnectionManagerError> {
+        Self::with_builders(Pool::builder(), replica, Pool::builder(), primary).await
+    }
+
+    /// Like `new`, with configured `bb8::Builder`s for the read and write
+    /// pools.
+    pub async fn with_builders(
+        read_builder: bb8::Builder<LibsqlConnectionManager>,
+        replica: LibsqlConnectionManager,
+        write_builder: bb8::Builder<LibsqlConnectionManager>,
+        primary: LibsqlConnectionManager,
+    ) -> Result<Self, ConnectionManagerError> {
+        if !matches!(replica.config.source, Source::RemoteReplica(..)) {
+            return Err(config_error("the read side of a ReadWriteSplit must be a remote replica"));
+        }
+        if !matches!(primary.config.source, Source::Remote(..)) {
+            return Err(config_error("the write side of a ReadWriteSplit must be a remote database"));
+        }
+
+        let read = read_builder.build(replica.clone()).await?;
+        let write = write_builder.build(primary).await?;
+        Ok(Self { replica, read, write })
+    }
+
+    /// Retrieves a connection to the local replica.
+    pub async fn read(&self) -> Result<PooledConnection<'_, LibsqlConnectionManager>, RunError<ConnectionManagerError>> {
+        self.read.get().await
+    }
+
+    /// Retrieves a connection to the primary.
+    pub async fn write(&self) -> Result<PooledConnection<'_, LibsqlConnectionManager>, RunError<ConnectionManagerError>> {
+        self.write.get().await
+    }
+
+    /// Syncs the replica, so `read` sees every write committed on the primary
+    /// before the call.
+    pub async fn sync(&self) -> Result<Replicated, ConnectionManagerError> {
+        self.replica.sync().await
+    }
+
+    /// The pool over the replica.
+    pub fn read_pool(&self) -> &Pool<LibsqlConnectionManager> {
+        &self.read
+    }
+
+    /// The pool over the primary.
+    pub fn write_pool(&self) -> &Pool<LibsqlConnectionManager> {
+        &self.write
+    }
+}
+
+/// Pools for several databases that split the data between them, with each
+/// key routed to one pool by hashing it.
+///
+/// Keys are routed on their bytes, hashed with a fixed hash, so a key maps to
+/// the same shard across processes, restarts, platforms and Rust releases as
+/// long as the number of shards stays the same; adding or removing a shard
+/// remaps most keys. Route integer ids on a fixed-width encoding such as
+/// `id.to_be_bytes()`, never on `usize`.
+/// Each shard is a separate database: there are no transactions, joins or
+/// constraints across shards, and a write to several shards can partly
+/// succeed.
+#[derive(Debug, Clone)]
+pub struct ShardedPool {
+    shards: Vec<Pool<LibsqlConnectionManager>>,
+}
+
+impl ShardedPool {
+    /// Routes keys over `shards`, in order. Fails if `shards` is empty.
+    pub fn new(shards: Vec<Pool<LibsqlConnectionManager>>) -> Result<Self, ConnectionManagerError> {
+        if shards.is_empty() {
+            return Err(config_error("a sharded pool needs at least one shard"));
+        }
+        Ok(Self { shards })
+    }
+
+    /// Returns the index of the shard `key` is routed to.
+    pub fn shard_for<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> usize {
+        // FNV spreads short keys such as integers poorly over the low bits, so
+        // mix it with MurmurHash3's finalizer before taking the modulus.
+        let mut hash = fnv1a(key.as_ref());
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^= hash >> 33;
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    /// Returns the pool of the shard `key` is routed to.
+    pub fn pool<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> &Pool<LibsqlConnectionManager> {
+        &self.shards[self.shard_for(key)]
+    }
+
+    /// Retrieves a connection from the shard `key` is routed to.
+    pub async fn get<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Result<PooledConnection<'_, LibsqlConnectionManager>, RunError<ConnectionManagerError>> {
+        self.pool(key).get().await
+    }
+
+    /// The pools of all shards, in routing order.
+    pub fn shards(&self) -> &[Pool<LibsqlConnectionManager>] {
+        &self.shards
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Connections pinned to keys for the length of a scope such as one request,
+/// so work for the same key always runs on the same connection and sees its
+/// own uncommitted writes.
+///
+/// Every pinned connection stays checked out until it is released or the
+/// scope is dropped. Pinning more keys than the pool has connections makes
+/// `get_pinned` wait for a connection this scope holds, until bb8's
+/// connection timeout, so keep scopes short and the number of keys bounded.
+pub struct PinnedConnections<'a, K> {
+    pool: &'a Pool<LibsqlConnectionManager>,
+    connections: HashMap<K, PooledConnection<'a, LibsqlConnectionManager>>,
+}
+
+impl<K: fmt::Debug> fmt::Debug for PinnedConnections<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PinnedConnections")
+            .field("keys", &self.connections.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a, K: Eq + Hash> PinnedConnections<'a, K> {
+    /// Starts an empty scope over `pool`.
+    pub fn new(pool: &'a Pool<LibsqlConnectionManager>) -> Self {
+        Self { pool, connections: HashMap::new() }
+    }
+
+    /// Returns the connection pinned to `key`, checking one out of the pool
+    /// on first use.
+    pub async fn get_pinned(&mut self, key: K) -> Result<&PooledConnection<'a, LibsqlConnectionManager>, ConnectionManagerError> {
+        let pool = self.pool;
+        Ok(match self.connections.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(pool.get().await?),
+        })
+    }
+
+    /// Returns the connection pinned to `key` to the pool. Returns `false` if
+    /// no connection was pinned to it.
+    pub fn release(&mut self, key: &K) -> bool {
+        self.connections.remove(key).is_some()
+    }
+}
+
+/// A column as reported by `PRAGMA table_info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnInfo {
+    pub cid: i64,
+    pub name: String,
+    pub decl_type: String,
+    pub not_null: bool,
+    pub default_value: Option<String>,
+    /// 1-based position in the primary key, or 0 if not part of it.
+    pub primary_key: i64,
+}
+
+/// Schema checks on a pool, each run on a connection checked out for the call.
+#[async_trait]
+pub trait PoolExt {
+    /// Returns `true` if a table named `name` exists.
+    async fn table_exists(&self, name: &str) -> Result<bool, ConnectionManagerError>;
+
+    /// Returns the columns of `table`, in declaration order.
+    async fn columns(&self, table: &str) -> Result<Vec<ColumnInfo>, ConnectionManagerError>;
+
+    /// Returns `true` if a connection can be checked out and answers
+    /// `SELECT 1` within two seconds, for health endpoints. Uses a single
+    /// connection, so a saturated pool reports unhealthy rather than waiting.
+    async fn healthy(&self) -> bool;
+}
+
+#[async_trait]
+impl PoolExt for Pool<LibsqlConnectionManager> {
+    async fn table_exists(&self, name: &str) -> Result<bool, ConnectionManagerError> {
+        let conn = self.get().await?;
+        let found = query_scalar::<i64>(
+            &conn,
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1;",
+            [name],
+        ).await?;
+        Ok(found.is_some())
+    }
+
+    async fn columns(&self, table: &str) -> Result<Vec<ColumnInfo>, ConnectionManagerError> {
+        let conn = self.get().await?;
+        let mut rows = conn.query(
+            "SELECT cid, name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1);",
+            [table],
+        ).await?;
+
+        let mut columns = Vec::new();
+        while let Some(row) = rows.next().await? {
+            columns.push(ColumnInfo {
+                cid: row.get(0)?,
+                name: row.get(1)?,
+                decl_type: row.get(2)?,
+                not_null: row.get::<i64>(3)? != 0,
+                default_value: row.get(4)?,
+                primary_key: row.get(5)?,
+            });
+        }
+
+        Ok(columns)
+    }
+
+    async fn healthy(&self) -> bool {
+        let check = async {
+            let conn = self.get().await?;
+            conn.execute_batch("SELECT 1;").await?;
+            Ok::<_, ConnectionManagerError>(())
+        };
+        matches!(tokio::time::timeout(HEALTH_CHECK_TIMEOUT, check).await, Ok(Ok(())))
+    }
+}
+
+/// A snapshot of how many connections of a pool are in use, see
+/// `utilization`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolUtilization {
+    /// Connections checked out of the pool.
+    pub in_use: u32,
+    /// Open connections waiting in the pool.
+    pub idle: u32,
+    /// The pool's `max_size`.
+    pub max: u32,
+}
+
+impl PoolUtilization {
+    /// The fraction of `max` in use, from `0.0` to `1.0`.
+    pub fn ratio(&self) -> f64 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        f64::from(self.in_use) / f64::from(self.max)
+    }
+}
+
+/// Samples how many connections of `pool` are in use, from `bb8::Pool::state`.
+/// bb8 does not expose the configured `max_size`, so pass the value given to
+/// `bb8::Builder::max_size` (10 by default). Sample it over time, e.g. with
+/// `spawn_utilization_logger`, to right-size `max_size`.
+pub fn utilization(pool: &Pool<LibsqlConnectionManager>, max_size: u32) -> PoolUtilization {
+    let state = pool.state();
+    PoolUtilization {
+        in_use: state.connections.saturating_sub(state.idle_connections),
+        idle: state.idle_connections,
+        max: max_size,
+    }
+}
+
+/// Spawns a tokio task logging `utilization` at `info` level every
+/// `interval`, starting one `interval` from now. The task keeps `pool` alive;
+/// abort the returned handle to stop it.
+#[cfg(feature = "tracing")]
+pub fn spawn_utilization_logger(pool: Pool<LibsqlConnectionManager>, max_size: u32, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            let _ = ticks.tick().await;
+            let sample = utilization(&pool, max_size);
+            tracing::info!(in_use = sample.in_use, idle = sample.idle, max = sample.max, "libsql pool utilization");
+        }
+    })
+}
+
+/// Builds a pool of `size` connections and opens all of them before
+/// returning, failing if any cannot be opened, so a service does not start
+/// with a degraded pool. Remote connections are only made on first use, so
+/// this turns on `validate_on_create` to run a query on each. bb8 retries
+/// each connection until its `connection_timeout`; use
+/// `build_and_warm_with` to shorten it.
+pub async fn build_and_warm(manager: LibsqlConnectionManager, size: u32) -> Result<Pool<LibsqlConnectionManager>, ConnectionManagerError> {
+    build_and_warm_with(Pool::builder(), manager, size).await
+}
+
+/// Like `build_and_warm`, with a configured `bb8::Builder`. Its `max_size`
+/// and `min_idle` are replaced by `size`.
+pub async fn build_and_warm_with(
+    builder: bb8::Builder<LibsqlConnectionManager>,
+    manager: LibsqlConnectionManager,
+    size: u32,
+) -> Result<Pool<LibsqlConnectionManager>, ConnectionManagerError> {
+    if size == 0 {
+        return Err(config_error("build_and_warm needs a size of at least one connection"));
+    }
+    if size > 1 && manager.config.wal_index_in_memory {
+        return Err(config_error("wal_index_in_memory needs a pool of one connection"));
+    }
+    let manager = manager.validate_on_create(true).build()?;
+    builder.max_size(size).min_idle(Some(size)).build(manager).await
+}
+
+/// Waits until a connection can be checked out of `pool` and answers
+/// `SELECT 1`, retrying with a growing delay, e.g. at startup while sqld is
+/// still coming up. Fails once `deadline` has passed, with the last error
+/// seen or `ConnectionManagerError::Timeout` if no connection could be
+/// checked out.
+///
+/// bb8 does not return connect failures from `get`: it retries them in the
+/// background, reports them to the pool's `error_sink`, and times the
+/// checkout out after `connection_timeout`. Set an `error_sink` to see why
+/// the database is not ready, and keep `connection_timeout` below `deadline`
+/// so a checkout does not use up the whole wait.
+pub async fn wait_ready(pool: &Pool<LibsqlConnectionManager>, deadline: Duration) -> Result<(), ConnectionManagerError> {
+    let deadline = tokio::time::Instant::now() + deadline;
+    let mut delay = WAIT_READY_MIN_DELAY;
+    let mut last_error = None;
+    loop {
+        let attempt = async {
+            let conn = pool.get().await?;
+            conn.execute_batch("SELECT 1;").await?;
+            Ok::<_, ConnectionManagerError>(())
+        };
+        match tokio::time::timeout_at(deadline, attempt).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(err)) => last_error = Some(err),
+            Err(_) => {},
+        }
+
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Err(last_error.unwrap_or(ConnectionManagerError::Timeout));
+        }
+        tokio::time::sleep_until((now + delay).min(deadline)).await;
+        delay = (delay * 2).min(WAIT_READY_MAX_DELAY);
+    }
+}
+
+/// Migrates the database to schema version `current`, tracked in
+/// `PRAGMA user_version`.
+///
+/// Every migration whose version is above the stored one and at most
+/// `current` runs in version order, each in its own transaction that also
+/// bumps `user_version`, so a failed migration leaves the database at the
+/// previous version. Fails if the database is already newer than `current`.
+///
+/// Each transaction takes the write lock up front and reads `user_version`
+/// again under it, so instances starting together apply each migration once.
+/// Not supported for replicas, which reject `PRAGMA user_version = …`.
+pub async fn ensure_schema_version(
+    pool: &Pool<LibsqlConnectionManager>,
+    current: i32,
+    migrations: &[(i32, &str)],
+) -> Result<(), ConnectionManagerError> {
+    let conn = pool.get().await?;
+    if conn.replica {
+        return Err(config_error("ensure_schema_version is not supported for replicas"));
+    }
+    check_schema_version(query_scalar(&conn, "PRAGMA user_version;", ()).await?.unwrap_or_default(), current)?;
+
+    let mut pending: Vec<_> = migrations.iter()
+        .filter(|(version, _)| *version <= current)
+        .collect();
+    pending.sort_by_key(|(version, _)| *version);
+
+    for (version, sql) in pending {
+        let tx = query::begin(&conn, TransactionBehavior::Immediate).await?;
+        let stored = query_scalar(&tx, "PRAGMA user_version;", ()).await?.unwrap_or_default();
+        check_schema_version(stored, current)?;
+        if *version <= stored {
+            tx.rollback().await?;
+            continue;
+        }
+        tx.execute_batch(sql).await?;
+        tx.execute_batch(&format!("PRAGMA user_version = {};", version)).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+fn check_schema_version(stored: i32, current: i32) -> Result<(), ConnectionManagerError> {
+    if stored > current {
+        return Err(config_error(format!("database schema version {} is newer than {}", stored, current)));
+    }
+    Ok(())
+}
@@ -0,0 +1,440 @@
+//! Helpers for running queries on pooled connections.
+//!
+//! The helpers take a `LibsqlConnection`, so their statements run the
+//! manager's query hooks, such as `LibsqlConnectionManager::query_logger`. A
+//! `libsql::Connection` from elsewhere can be wrapped with
+//! `LibsqlConnection::from`.
+
+use std::future::Future;
+use std::ops::Deref;
+use std::time::Duration;
+
+use futures::Stream;
+use libsql::{params::IntoParams, Row, Rows};
+#[cfg(any(feature = "csv", feature = "serde_json"))]
+use libsql::Value;
+
+use crate::connection::LibsqlConnection;
+use crate::errors::ConnectionManagerError;
+use crate::row::FromLibsqlRow;
+use crate::value::FromValue;
+
+pub use libsql::TransactionBehavior;
+
+/// A result column of a query, see `query_columns`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultColumn {
+    pub name: String,
+    /// The declared type of the table column this result comes from, or
+    /// `None` for expressions.
+    pub decl_type: Option<String>,
+}
+
+/// A step of a query plan, see `explain`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainRow {
+    pub id: i64,
+    /// The `id` of the step this one is nested under, `0` at the top level.
+    pub parent: i64,
+    /// SQLite's description of the step, e.g. `SEARCH users USING INDEX
+    /// users_email (email=?)`.
+    pub detail: String,
+}
+
+/// Prepares `sql` without running it and returns its result columns, e.g.
+/// to build headers for an exporter. Remote statements are not described
+/// until they run, so for remote databases this is empty; use
+/// `column_names` on the `Rows` instead.
+pub async fn query_columns(conn: &LibsqlConnection, sql: &str) -> Result<Vec<ResultColumn>, ConnectionManagerError> {
+    let stmt = conn.run(sql, conn.prepare(sql)).await?;
+    Ok(stmt.columns().iter()
+        .map(|column| ResultColumn {
+            name: column.name().to_string(),
+            decl_type: column.decl_type().map(str::to_string),
+        })
+        .collect())
+}
+
+/// Runs `EXPLAIN QUERY PLAN` for `sql` and returns the plan's steps in
+/// order, to check whether a slow query uses the expected indexes.
+pub async fn explain(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<Vec<ExplainRow>, ConnectionManagerError> {
+    let mut rows = conn.query(&format!("EXPLAIN QUERY PLAN {}", sql), params).await?;
+    let mut plan = Vec::new();
+    while let Some(row) = rows.next().await? {
+        plan.push(ExplainRow {
+            id: row.get(0)?,
+            parent: row.get(1)?,
+            detail: row.get(3)?,
+        });
+    }
+    Ok(plan)
+}
+
+/// Returns the column names of `rows`, available before the first row is read.
+pub fn column_names(rows: &Rows) -> Vec<String> {
+    (0..rows.column_count())
+        .map(|idx| rows.column_name(idx).unwrap_or_default().to_string())
+        .collect()
+}
+
+/// Runs `sql` and returns its rows as a `Stream`, so results can be consumed
+/// with `TryStreamExt::try_collect`, `StreamExt::for_each` and friends.
+/// Subject to `LibsqlConnectionManager::max_rows`, see
+/// `LibsqlConnection::query_stream`.
+pub async fn query_stream(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<impl Stream<Item = Result<Row, ConnectionManagerError>>, ConnectionManagerError> {
+    conn.query_stream(sql, params).await
+}
+
+/// Runs `sql` and returns the first column of the first row, or `None` if the
+/// query returned no rows.
+///
+/// A NULL column is read as `Some(None)` when `T` is an `Option`, so it can be
+/// told apart from no rows, and fails with `ConnectionManagerError::NullValue`
+/// otherwise, e.g. for `SELECT max(id)` over an empty table.
+pub async fn query_scalar<T: FromValue>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<Option<T>, ConnectionManagerError> {
+    let mut rows = conn.query(sql, params).await?;
+    let value = match rows.next().await? {
+        Some(row) => row.get_value(0)?,
+        None => return Ok(None),
+    };
+    let is_null = matches!(value, libsql::Value::Null);
+    match T::from_value(value) {
+        Err(_) if is_null => Err(ConnectionManagerError::NullValue(std::any::type_name::<T>().to_string())),
+        result => result.map(Some),
+    }
+}
+
+/// Runs `sql` and maps every row with `T::from_row`. Subject to
+/// `LibsqlConnectionManager::max_rows`, see `LibsqlConnection::query_all`.
+pub async fn query_as<T: FromLibsqlRow>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<Vec<T>, ConnectionManagerError> {
+    conn.query_all(sql, params).await
+}
+
+/// Runs `sql` and maps every row with `f`, like rusqlite's `query_map`,
+/// stopping at the first error.
+pub async fn query_map<T, F>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+    mut f: F,
+) -> Result<Vec<T>, ConnectionManagerError>
+where
+    F: FnMut(&Row) -> Result<T, ConnectionManagerError>,
+{
+    let mut rows = conn.query(sql, params).await?;
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if !conn.admit_row(values.len())? {
+            break;
+        }
+        values.push(f(&row)?);
+    }
+    Ok(values)
+}
+
+/// Like `query_as`, but gives up with `ConnectionManagerError::Cancelled` as
+/// soon as `token` is cancelled, e.g. when a request-scoped timeout fires.
+///
+/// Remote requests are aborted by dropping them. Local statements run on the
+/// calling task, so they only observe the token between rows; the statement
+/// is finalized when the partially read rows are dropped.
+#[cfg(feature = "cancellation")]
+pub async fn query_cancellable<T: FromLibsqlRow>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+    token: &tokio_util::sync::CancellationToken,
+) -> Result<Vec<T>, ConnectionManagerError> {
+    use futures::future::{self, Either};
+
+    // Local rows are produced without yielding, so give the task that
+    // cancels the token a chance to run every so often.
+    const YIELD_EVERY: usize = 256;
+
+    let cancelled = token.cancelled();
+    futures::pin_mut!(cancelled);
+    let query = conn.query(sql, params);
+    futures::pin_mut!(query);
+    let mut rows = match future::select(cancelled.as_mut(), query).await {
+        Either::Left(_) => return Err(ConnectionManagerError::Cancelled),
+        Either::Right((rows, _)) => rows?,
+    };
+
+    let mut values = Vec::new();
+    loop {
+        if values.len() % YIELD_EVERY == YIELD_EVERY - 1 {
+            tokio::task::yield_now().await;
+        }
+        let next = rows.next();
+        futures::pin_mut!(next);
+        match future::select(cancelled.as_mut(), next).await {
+            Either::Left(_) => return Err(ConnectionManagerError::Cancelled),
+            Either::Right((Ok(Some(row)), _)) => {
+                if !conn.admit_row(values.len())? {
+                    return Ok(values);
+                }
+                values.push(T::from_row(&row)?);
+            },
+            Either::Right((Ok(None), _)) => return Ok(values),
+            Either::Right((Err(err), _)) => return Err(err.into()),
+        }
+    }
+}
+
+/// Runs a write statement and returns the number of rows it changed.
+pub async fn execute(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<u64, ConnectionManagerError> {
+    conn.execute(sql, params).await
+}
+
+/// Runs the semicolon-separated statements in `sql` and returns the number
+/// of rows they changed in total, which `Connection::execute_batch` does not
+/// report. The count is the growth of `Connection::total_changes` across the
+/// batch; `Connection::changes` still reports the last statement only.
+pub async fn execute_batch(conn: &LibsqlConnection, sql: &str) -> Result<u64, ConnectionManagerError> {
+    let before = conn.total_changes();
+    conn.execute_batch(sql).await?;
+    Ok(conn.total_changes().saturating_sub(before))
+}
+
+/// Runs an `INSERT` and returns the rowid of the inserted row. The rowid is
+/// read from `conn` right after the statement, so it is not affected by
+/// inserts on other pooled connections.
+pub async fn insert(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<i64, ConnectionManagerError> {
+    let _ = conn.execute(sql, params).await?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Runs the write statement `sql` once per item of `rows`, preparing it only
+/// once, and returns the total number of rows changed. Everything runs in one
+/// transaction, so an error part way rolls back the rows already inserted.
+pub async fn insert_many<I, P>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    rows: I,
+) -> Result<u64, ConnectionManagerError>
+where
+    I: IntoIterator<Item = P>,
+    P: IntoParams,
+{
+    let tx = begin(conn, TransactionBehavior::Deferred).await?;
+    let result = async {
+        let mut stmt = tx.prepare(sql).await?;
+        let mut changed = 0;
+        for params in rows {
+            let params = tx.check_parameters(params)?;
+            changed += tx.run(sql, stmt.execute(params)).await? as u64;
+            stmt.reset();
+        }
+        Ok::<_, ConnectionManagerError>(changed)
+    }.await;
+
+    match result {
+        Ok(changed) => {
+            tx.commit().await?;
+            Ok(changed)
+        },
+        Err(err) => {
+            let _ = tx.rollback().await;
+            Err(err)
+        },
+    }
+}
+
+/// An open transaction started with `begin`. It derefs to the
+/// `LibsqlConnection` it runs on, so statements run inside the transaction.
+/// Dropping it without `commit` rolls it back.
+pub struct Transaction {
+    tx: libsql::Transaction,
+    conn: LibsqlConnection,
+}
+
+impl Transaction {
+    /// Commits the transaction.
+    pub async fn commit(self) -> Result<(), ConnectionManagerError> {
+        Ok(self.tx.commit().await?)
+    }
+
+    /// Rolls the transaction back.
+    pub async fn rollback(self) -> Result<(), ConnectionManagerError> {
+        Ok(self.tx.rollback().await?)
+    }
+}
+
+impl Deref for Transaction {
+    type Target = LibsqlConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+/// Starts a transaction on `conn` with the given behavior. Use
+/// `TransactionBehavior::Immediate` to take the write lock up front, so a
+/// writer fails or waits at `BEGIN` instead of part way through when another
+/// connection holds the lock.
+pub async fn begin(conn: &LibsqlConnection, behavior: TransactionBehavior) -> Result<Transaction, ConnectionManagerError> {
+    Ok(Transaction {
+        tx: conn.transaction_with_behavior(behavior).await?,
+        conn: conn.share(),
+    })
+}
+
+/// Starts a read-only transaction on `conn` and pins its snapshot right
+/// away, so every query run on it, e.g. for a report, sees the database as
+/// of this call and none of the writes committed meanwhile. End it with
+/// `commit` or `rollback`, which are equivalent here.
+///
+/// In WAL mode writers carry on while the snapshot is open, but the WAL
+/// cannot be checkpointed past it, so keep it short. In rollback journal
+/// mode the snapshot holds a shared lock instead, and writers on other
+/// connections fail as busy until it ends.
+pub async fn begin_snapshot(conn: &LibsqlConnection) -> Result<Transaction, ConnectionManagerError> {
+    let tx = begin(conn, TransactionBehavior::ReadOnly).await?;
+    // A transaction only takes its snapshot at the first read.
+    tx.execute_batch("SELECT 1 FROM sqlite_master LIMIT 1;").await?;
+    Ok(tx)
+}
+
+/// Runs `f` in an immediate transaction and commits it, rolling back if `f`
+/// fails. If any step fails with a busy error (see
+/// `ConnectionManagerError::is_busy`), the transaction is rolled back and `f`
+/// runs again, up to `max_retries` more times with a growing delay. `f` must
+/// therefore only have side effects through the transaction it is given.
+///
+/// `f` receives a `LibsqlConnection` sharing the underlying connection and
+/// query hooks of `conn`, so it runs inside the transaction.
+pub async fn transaction_retry<T, F, Fut>(
+    conn: &LibsqlConnection,
+    max_retries: u32,
+    mut f: F,
+) -> Result<T, ConnectionManagerError>
+where
+    F: FnMut(LibsqlConnection) -> Fut,
+    Fut: Future<Output = Result<T, ConnectionManagerError>>,
+{
+    let mut retries = 0;
+    loop {
+        match run_transaction(conn, &mut f).await {
+            Err(err) if err.is_busy() && retries < max_retries => {
+                retries += 1;
+                tokio::time::sleep(Duration::from_millis(10 * u64::from(retries))).await;
+            },
+            result => return result,
+        }
+    }
+}
+
+async fn run_transaction<T, F, Fut>(conn: &LibsqlConnection, f: &mut F) -> Result<T, ConnectionManagerError>
+where
+    F: FnMut(LibsqlConnection) -> Fut,
+    Fut: Future<Output = Result<T, ConnectionManagerError>>,
+{
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).await?;
+    let result = match f(conn.share()).await {
+        Ok(value) => tx.commit().await.map(|_| value).map_err(ConnectionManagerError::from),
+        Err(err) => Err(err),
+    };
+    // A failed body or a busy COMMIT leaves the transaction open.
+    if result.is_err() && !conn.is_autocommit() {
+        let _ = conn.execute_batch("ROLLBACK;").await;
+    }
+    result
+}
+
+/// Runs `sql` and returns each row as a JSON object keyed by column name.
+/// `NULL`s map to `null` and blobs to base64 strings.
+#[cfg(feature = "serde_json")]
+pub async fn query_json(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+) -> Result<Vec<serde_json::Value>, ConnectionManagerError> {
+    use base64::Engine;
+
+    let mut rows = conn.query(sql, params).await?;
+    let columns = column_names(&rows);
+
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if !conn.admit_row(values.len())? {
+            break;
+        }
+        let mut object = serde_json::Map::with_capacity(columns.len());
+        for (idx, column) in columns.iter().enumerate() {
+            let value = match row.get_value(idx as i32)? {
+                Value::Null => serde_json::Value::Null,
+                Value::Integer(value) => value.into(),
+                Value::Real(value) => serde_json::Number::from_f64(value)
+                    .map_or(serde_json::Value::Null, serde_json::Value::Number),
+                Value::Text(value) => value.into(),
+                Value::Blob(value) => base64::engine::general_purpose::STANDARD.encode(value).into(),
+            };
+            object.insert(column.clone(), value);
+        }
+        values.push(serde_json::Value::Object(object));
+    }
+
+    Ok(values)
+}
+
+/// Runs `sql` and writes its rows to `writer` as CSV, preceded by a header
+/// row of column names. `NULL`s are written as empty fields and blobs as
+/// lowercase hex.
+#[cfg(feature = "csv")]
+pub async fn query_csv<W: std::io::Write>(
+    conn: &LibsqlConnection,
+    sql: &str,
+    params: impl IntoParams,
+    writer: W,
+) -> Result<(), ConnectionManagerError> {
+    let mut rows = conn.query(sql, params).await?;
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(column_names(&rows)).map_err(std::io::Error::from)?;
+
+    let mut record = Vec::with_capacity(rows.column_count() as usize);
+    let mut read = 0;
+    while let Some(row) = rows.next().await? {
+        if !conn.admit_row(read)? {
+            break;
+        }
+        read += 1;
+        record.clear();
+        for idx in 0..rows.column_count() {
+            record.push(match row.get_value(idx)? {
+                Value::Null => String::new(),
+                Value::Integer(value) => value.to_string(),
+                Value::Real(value) => value.to_string(),
+                Value::Text(value) => value,
+                Value::Blob(value) => value.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            });
+        }
+        writer.write_record(&record).map_err(std::io::Error::from)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
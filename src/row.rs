@@ -0,0 +1,40 @@
+//! Mapping `libsql::Row`s into user types.
+
+use libsql::Row;
+
+use crate::errors::ConnectionManagerError;
+use crate::value::FromValue;
+
+/// Types that can be built from a single `libsql::Row`, for `query::query_as`.
+///
+/// ```no_run
+/// use bb8_libsql::errors::ConnectionManagerError;
+/// use bb8_libsql::row::{column, FromLibsqlRow};
+///
+/// struct User {
+///     id: i64,
+///     name: String,
+///     email: Option<String>,
+/// }
+///
+/// impl FromLibsqlRow for User {
+///     fn from_row(row: &libsql::Row) -> Result<Self, ConnectionManagerError> {
+///         Ok(User {
+///             id: column(row, "id")?,
+///             name: column(row, "name")?,
+///             email: column(row, "email")?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromLibsqlRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, ConnectionManagerError>;
+}
+
+/// Reads the column named `name` out of `row`.
+pub fn column<T: FromValue>(row: &Row, name: &str) -> Result<T, ConnectionManagerError> {
+    let idx = (0..row.column_count() as i32)
+        .find(|&idx| row.column_name(idx) == Some(name))
+        .ok_or_else(|| libsql::Error::InvalidColumnName(name.to_string()))?;
+    T::from_value(row.get_value(idx)?)
+}
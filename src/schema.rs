@@ -0,0 +1,121 @@
+//! The schema of a database, as recorded in `sqlite_master`.
+
+use std::sync::Arc;
+
+use bb8::Pool;
+use libsql::Connection;
+use tokio::sync::RwLock;
+
+use crate::errors::ConnectionManagerError;
+use crate::LibsqlConnectionManager;
+
+/// A table, index, view or trigger of a `Schema`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaObject {
+    pub name: String,
+    /// The table the object belongs to; its own name for tables and views.
+    pub table: String,
+    /// The `CREATE` statement, or `None` for indexes SQLite creates itself,
+    /// such as those backing `UNIQUE` constraints.
+    pub sql: Option<String>,
+}
+
+/// The tables, indexes, views and triggers of the main database, in
+/// `sqlite_master` order. Internal `sqlite_` tables are left out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<SchemaObject>,
+    pub indexes: Vec<SchemaObject>,
+    pub views: Vec<SchemaObject>,
+    pub triggers: Vec<SchemaObject>,
+}
+
+impl Schema {
+    /// Reads the schema from `sqlite_master`.
+    pub async fn load(conn: &Connection) -> Result<Self, ConnectionManagerError> {
+        let mut rows = conn.query(
+            "SELECT type, name, tbl_name, sql FROM sqlite_master WHERE NOT (type = 'table' AND name LIKE 'sqlite\\_%' ESCAPE '\\');",
+            (),
+        ).await?;
+
+        let mut schema = Self::default();
+        while let Some(row) = rows.next().await? {
+            let kind = row.get::<String>(0)?;
+            let object = SchemaObject {
+                name: row.get(1)?,
+                table: row.get(2)?,
+                sql: row.get(3)?,
+            };
+            match kind.as_str() {
+                "table" => schema.tables.push(object),
+                "index" => schema.indexes.push(object),
+                "view" => schema.views.push(object),
+                "trigger" => schema.triggers.push(object),
+                _ => {},
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns the table called `name`, compared case-insensitively like
+    /// SQLite does.
+    pub fn table(&self, name: &str) -> Option<&SchemaObject> {
+        find(&self.tables, name)
+    }
+
+    /// Returns the index called `name`.
+    pub fn index(&self, name: &str) -> Option<&SchemaObject> {
+        find(&self.indexes, name)
+    }
+
+    /// Returns the view called `name`.
+    pub fn view(&self, name: &str) -> Option<&SchemaObject> {
+        find(&self.views, name)
+    }
+
+    /// Returns the trigger called `name`.
+    pub fn trigger(&self, name: &str) -> Option<&SchemaObject> {
+        find(&self.triggers, name)
+    }
+
+    /// Returns the indexes on `table`.
+    pub fn indexes_on<'a>(&'a self, table: &'a str) -> impl Iterator<Item = &'a SchemaObject> + 'a {
+        self.indexes.iter().filter(move |index| index.table.eq_ignore_ascii_case(table))
+    }
+}
+
+fn find<'a>(objects: &'a [SchemaObject], name: &str) -> Option<&'a SchemaObject> {
+    objects.iter().find(|object| object.name.eq_ignore_ascii_case(name))
+}
+
+/// A `Schema` loaded once through a pool and shared until `invalidate`d,
+/// e.g. by a query builder checking column references. Keep one per pool and
+/// invalidate it after migrations.
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    schema: RwLock<Option<Arc<Schema>>>,
+}
+
+impl SchemaCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached schema, loading it with a connection from `pool`
+    /// on first use.
+    pub async fn get(&self, pool: &Pool<LibsqlConnectionManager>) -> Result<Arc<Schema>, ConnectionManagerError> {
+        if let Some(schema) = self.schema.read().await.as_ref() {
+            return Ok(schema.clone());
+        }
+
+        let conn = pool.get().await?;
+        let schema = Arc::new(Schema::load(&conn).await?);
+        *self.schema.write().await = Some(schema.clone());
+        Ok(schema)
+    }
+
+    /// Drops the cached schema, so the next `get` loads it again.
+    pub async fn invalidate(&self) {
+        *self.schema.write().await = None;
+    }
+}
@@ -0,0 +1,306 @@
+//! Conversions between `libsql::Value` and Rust types.
+//!
+//! SQLite has no date or time type. With the `chrono` feature, datetimes are
+//! read from ISO-8601 text (as written by SQLite's `datetime()` and by
+//! `datetime_text`) or from integer Unix timestamps, and text without an
+//! offset is taken to be UTC.
+
+use std::convert::TryFrom;
+
+use libsql::Value;
+
+use crate::errors::ConnectionManagerError;
+
+/// Types that can be read out of a single `libsql::Value`.
+///
+/// This mirrors libsql's own (sealed) `FromValue`, so helpers in this crate
+/// can be generic over the requested column type.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError>;
+}
+
+/// Types that can be written into a single `libsql::Value`, including those
+/// libsql has no `From` impl for, such as `u64` and timestamps.
+pub trait ToValue {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError>;
+}
+
+/// Typed reads of a `libsql::Value` through `FromValue`.
+///
+/// ```
+/// use bb8_libsql::value::ValueExt;
+///
+/// let value = libsql::Value::Integer(7);
+/// assert_eq!(value.get::<i64>().unwrap(), 7);
+/// assert!(value.get::<String>().is_err());
+/// assert_eq!(libsql::Value::Null.get::<Option<i64>>().unwrap(), None);
+/// ```
+pub trait ValueExt {
+    /// Converts a copy of the value to `T`.
+    fn get<T: FromValue>(&self) -> Result<T, ConnectionManagerError>;
+
+    /// Converts the value to `T`, without copying text or blobs.
+    fn into_typed<T: FromValue>(self) -> Result<T, ConnectionManagerError>;
+}
+
+impl ValueExt for Value {
+    fn get<T: FromValue>(&self) -> Result<T, ConnectionManagerError> {
+        T::from_value(self.clone())
+    }
+
+    fn into_typed<T: FromValue>(self) -> Result<T, ConnectionManagerError> {
+        T::from_value(self)
+    }
+}
+
+fn invalid_type() -> ConnectionManagerError {
+    libsql::Error::InvalidColumnType.into()
+}
+
+fn null_value() -> ConnectionManagerError {
+    libsql::Error::NullValue.into()
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        Ok(value)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Integer(value) => Ok(value),
+            Value::Null => Err(null_value()),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        i32::try_from(i64::from_value(value)?).map_err(|_| invalid_type())
+    }
+}
+
+impl FromValue for u32 {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        u32::try_from(i64::from_value(value)?).map_err(|_| invalid_type())
+    }
+}
+
+impl FromValue for u64 {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        u64::try_from(i64::from_value(value)?).map_err(|_| invalid_type())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Real(value) => Ok(value),
+            Value::Integer(value) => Ok(value as f64),
+            Value::Null => Err(null_value()),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match i64::from_value(value)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Text(value) => Ok(value),
+            Value::Null => Err(null_value()),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Blob(value) => Ok(value),
+            Value::Null => Err(null_value()),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(self.clone())
+    }
+}
+
+impl ToValue for i64 {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Integer(*self))
+    }
+}
+
+impl ToValue for i32 {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Integer(i64::from(*self)))
+    }
+}
+
+impl ToValue for u32 {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Integer(i64::from(*self)))
+    }
+}
+
+impl ToValue for u64 {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        i64::try_from(*self).map(Value::Integer).map_err(|_| libsql::Error::ToSqlConversionFailure(
+            format!("{} does not fit in an INTEGER column", self).into(),
+        ).into())
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Real(*self))
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Integer(i64::from(*self)))
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Text(self.to_string()))
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        self.as_str().to_value()
+    }
+}
+
+impl ToValue for [u8] {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Blob(self.to_vec()))
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        self.as_slice().to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        match self {
+            Some(value) => value.to_value(),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        (**self).to_value()
+    }
+}
+
+#[cfg(feature = "chrono")]
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Formats `datetime` as UTC text in SQLite's `YYYY-MM-DD HH:MM:SS.SSS`
+/// layout, which sorts chronologically and works with SQLite's date functions.
+#[cfg(feature = "chrono")]
+pub fn datetime_text<Tz: chrono::TimeZone>(datetime: &chrono::DateTime<Tz>) -> String {
+    datetime.naive_utc().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::NaiveDateTime {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Text(text) => {
+                if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(&text) {
+                    return Ok(datetime.naive_utc());
+                }
+                DATETIME_FORMATS.iter()
+                    .find_map(|format| chrono::NaiveDateTime::parse_from_str(&text, format).ok())
+                    .ok_or_else(invalid_type)
+            },
+            Value::Integer(seconds) => chrono::DateTime::from_timestamp(seconds, 0)
+                .map(|datetime| datetime.naive_utc())
+                .ok_or_else(invalid_type),
+            Value::Null => Err(null_value()),
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::DateTime<chrono::Utc> {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        chrono::NaiveDateTime::from_value(value).map(|datetime| datetime.and_utc())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::NaiveDate {
+    fn from_value(value: Value) -> Result<Self, ConnectionManagerError> {
+        match value {
+            Value::Text(text) => match chrono::NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+                Ok(date) => Ok(date),
+                Err(_) => chrono::NaiveDateTime::from_value(Value::Text(text)).map(|datetime| datetime.date()),
+            },
+            value => chrono::NaiveDateTime::from_value(value).map(|datetime| datetime.date()),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToValue for chrono::NaiveDateTime {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Text(self.format("%Y-%m-%d %H:%M:%S%.3f").to_string()))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> ToValue for chrono::DateTime<Tz> {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Text(datetime_text(self)))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToValue for chrono::NaiveDate {
+    fn to_value(&self) -> Result<Value, ConnectionManagerError> {
+        Ok(Value::Text(self.format("%Y-%m-%d").to_string()))
+    }
+}
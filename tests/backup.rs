@@ -0,0 +1,80 @@
+mod common;
+
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, TempDir, TOKEN, URL};
+
+async fn populated(dir: &TempDir) -> LibsqlConnectionManager {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1), (2), (3);").await.unwrap();
+    manager
+}
+
+async fn count_rows(path: &std::path::Path) -> i64 {
+    let conn = LibsqlConnectionManager::local(path).connect().await.unwrap();
+    scalar(&conn, "SELECT count(*) FROM t;").await
+}
+
+#[tokio::test]
+async fn backup_to_copies_the_data_and_replaces_the_destination() {
+    let dir = TempDir::new();
+    let manager = populated(&dir).await;
+    let dest = dir.join("backup.sqlite");
+    std::fs::write(&dest, b"stale").unwrap();
+
+    manager.backup_to(&dest).await.unwrap();
+    assert_eq!(count_rows(&dest).await, 3);
+}
+
+#[tokio::test]
+async fn backup_to_copies_a_replica() {
+    let dir = TempDir::new();
+    let path = dir.join("replica.db");
+    let manager = LibsqlConnectionManager::remote_replica(&path, "http://127.0.0.1:1", TOKEN, Duration::from_secs(3600))
+        .manual_sync_only()
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    // Stands in for rows a sync would have written to the replica.
+    let writer = libsql::Builder::new_local(&path).build().await.unwrap().connect().unwrap();
+    writer.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1), (2);").await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 2);
+
+    let dest = dir.join("backup.sqlite");
+    manager.backup_to(&dest).await.unwrap();
+    assert_eq!(count_rows(&dest).await, 2);
+}
+
+#[tokio::test]
+async fn backup_to_is_rejected_for_remote_databases() {
+    let dir = TempDir::new();
+    let err = LibsqlConnectionManager::remote(URL, TOKEN).backup_to(dir.join("backup.sqlite")).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn snapshot_into_copies_the_data() {
+    let dir = TempDir::new();
+    let manager = populated(&dir).await;
+    let dest = dir.join("snapshot.sqlite");
+
+    manager.snapshot_into(&dest).await.unwrap();
+    assert_eq!(count_rows(&dest).await, 3);
+}
+
+#[tokio::test]
+async fn snapshot_into_refuses_to_overwrite() {
+    let dir = TempDir::new();
+    let manager = populated(&dir).await;
+    let dest = dir.join("snapshot.sqlite");
+    std::fs::write(&dest, b"keep").unwrap();
+
+    let err = manager.snapshot_into(&dest).await.unwrap_err();
+    assert!(matches!(&err, ConnectionManagerError::IoError(err) if err.kind() == std::io::ErrorKind::AlreadyExists), "{}", err);
+    assert_eq!(std::fs::read(&dest).unwrap(), b"keep");
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "blocking")]
+
+mod common;
+
+use bb8_libsql::blocking::Pool;
+use bb8_libsql::LibsqlConnectionManager;
+use libsql::Value;
+
+use common::TempDir;
+
+#[test]
+fn a_blocking_pool_runs_queries_without_a_runtime() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let pool = Pool::with_builder(bb8::Pool::builder().max_size(1), manager).unwrap();
+
+    let conn = pool.get().unwrap();
+    conn.execute_batch("CREATE TABLE t (x, y);").unwrap();
+    assert_eq!(conn.execute("INSERT INTO t VALUES (?1, ?2), (2, NULL);", (1, "a")).unwrap(), 2);
+    drop(conn);
+
+    // The single connection was returned to the pool.
+    let rows = pool.get().unwrap().query("SELECT x, y FROM t ORDER BY x;", ()).unwrap();
+    assert_eq!(rows, [
+        vec![Value::Integer(1), Value::Text("a".to_string())],
+        vec![Value::Integer(2), Value::Null],
+    ]);
+}
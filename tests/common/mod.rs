@@ -0,0 +1,63 @@
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bb8_libsql::LibsqlConnectionManager;
+
+/// A directory under the system temp dir, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "bb8-libsql-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub const URL: &str = "libsql://db.example.com";
+
+pub const TOKEN: &str = "token";
+
+/// A manager for each kind of database besides a local one, for checking
+/// options that are only supported for local databases.
+pub fn non_local_managers(dir: &TempDir) -> Vec<LibsqlConnectionManager> {
+    vec![
+        LibsqlConnectionManager::remote(URL, TOKEN),
+        LibsqlConnectionManager::local_replica(dir.join("local-replica.db")),
+        LibsqlConnectionManager::remote_replica(dir.join("remote-replica.db"), URL, TOKEN, std::time::Duration::from_secs(60)),
+    ]
+}
+
+/// Returns the first column of the first row of `sql` as an integer.
+pub async fn scalar(conn: &libsql::Connection, sql: &str) -> i64 {
+    let mut rows = conn.query(sql, ()).await.unwrap();
+    rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap()
+}
+
+/// The url and token of a sqld server for the `#[ignore]`d tests that need
+/// one, from `LIBSQL_TEST_URL` and `LIBSQL_TEST_TOKEN`.
+pub fn server() -> (String, String) {
+    let url = std::env::var("LIBSQL_TEST_URL").expect("LIBSQL_TEST_URL is not set");
+    (url, std::env::var("LIBSQL_TEST_TOKEN").unwrap_or_default())
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::time::Duration;
+
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{TempDir, TOKEN, URL};
+
+fn config_error(manager: LibsqlConnectionManager) -> String {
+    match manager.build() {
+        Err(ConnectionManagerError::Config(message)) => message,
+        Err(err) => panic!("expected a Config error, got {}", err),
+        Ok(_) => panic!("expected a Config error"),
+    }
+}
+
+#[test]
+fn build_accepts_valid_configurations() {
+    let dir = TempDir::new();
+    LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    LibsqlConnectionManager::remote(URL, TOKEN).build().unwrap();
+    LibsqlConnectionManager::remote("https://db.example.com:8080/", TOKEN).build().unwrap();
+    LibsqlConnectionManager::remote_replica(dir.join("replica.db"), URL, TOKEN, Duration::from_secs(1)).build().unwrap();
+}
+
+#[test]
+fn build_rejects_bad_paths() {
+    let dir = TempDir::new();
+    assert!(config_error(LibsqlConnectionManager::local("")).contains("empty"));
+    assert!(config_error(LibsqlConnectionManager::local(dir.join("missing/db.sqlite"))).contains("does not exist"));
+}
+
+#[test]
+fn build_rejects_bad_urls() {
+    assert!(config_error(LibsqlConnectionManager::remote("db.example.com", TOKEN)).contains("no scheme"));
+    assert!(config_error(LibsqlConnectionManager::remote("ftp://db.example.com", TOKEN)).contains("unsupported scheme"));
+    assert!(config_error(LibsqlConnectionManager::remote("libsql://", TOKEN)).contains("no host"));
+}
+
+#[test]
+fn build_rejects_a_zero_sync_interval() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), URL, TOKEN, Duration::ZERO);
+    assert!(config_error(manager).contains("sync_interval"));
+}
+
+#[tokio::test]
+async fn check_config_never_connects() {
+    let dir = TempDir::new();
+    // Nothing listens there, so any connection attempt would fail.
+    LibsqlConnectionManager::remote("http://127.0.0.1:1", TOKEN).check_config().await.unwrap();
+    LibsqlConnectionManager::local(dir.join("db.sqlite")).check_config().await.unwrap();
+    assert!(!dir.join("db.sqlite").exists());
+}
+
+#[tokio::test]
+async fn check_config_rejects_a_directory_as_the_database() {
+    let dir = TempDir::new();
+    let err = LibsqlConnectionManager::local(dir.path()).check_config().await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(ref message) if message.contains("is a directory")), "{}", err);
+}
+
+#[test]
+fn build_rejects_zero_limits_and_intervals() {
+    let dir = TempDir::new();
+    let local = || LibsqlConnectionManager::local(dir.join("db.sqlite"));
+    assert!(config_error(local().max_parameters(0)).contains("max_parameters"));
+    assert!(config_error(local().max_concurrent_requests(0)).contains("max_concurrent_requests"));
+    assert!(config_error(local().auto_analyze(Duration::ZERO)).contains("auto_analyze"));
+    assert!(config_error(LibsqlConnectionManager::remote(URL, TOKEN).keepalive_interval(Duration::ZERO)).contains("keepalive_interval"));
+}
+
+#[test]
+fn build_rejects_missing_files() {
+    let dir = TempDir::new();
+    let local = || LibsqlConnectionManager::local(dir.join("db.sqlite"));
+    assert!(config_error(local().extensions([dir.join("missing.so")])).contains("does not exist"));
+    assert!(config_error(local().extensions_dir(dir.join("missing"))).contains("does not exist"));
+    assert!(config_error(local().temp_dir(dir.join("missing"))).contains("does not exist"));
+    assert!(config_error(LibsqlConnectionManager::remote_from_file(URL, dir.join("token"))).contains("does not exist"));
+}
+
+#[test]
+fn config_errors_have_no_source() {
+    use std::error::Error;
+
+    let err = ConnectionManagerError::Config("bad url".to_string());
+    assert_eq!(err.to_string(), "Config Error: bad url");
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn build_rejects_a_url_or_path_for_the_wrong_variant() {
+    let dir = TempDir::new();
+    assert!(config_error(LibsqlConnectionManager::local(URL)).contains("is a remote url"));
+    assert!(config_error(LibsqlConnectionManager::local("https://db.example.com")).contains("is a remote url"));
+    assert!(config_error(LibsqlConnectionManager::remote("file:///tmp/db.sqlite", TOKEN)).contains("is a local file"));
+    assert!(config_error(LibsqlConnectionManager::remote("/tmp/db.sqlite", TOKEN)).contains("no scheme"));
+
+    let replica = |path, url| LibsqlConnectionManager::remote_replica(path, url, TOKEN, Duration::from_secs(1));
+    assert!(config_error(replica(URL.into(), URL)).contains("is a remote url"));
+    assert!(config_error(replica(dir.join("replica.db"), "file:///tmp/db.sqlite")).contains("is a local file"));
+}
+
+#[test]
+fn config_fingerprint_matches_equal_configurations_only() {
+    let dir = TempDir::new();
+    let local = || LibsqlConnectionManager::local(dir.join("db.sqlite"));
+    let replica = |interval| LibsqlConnectionManager::remote_replica(dir.join("replica.db"), URL, TOKEN, interval);
+    let fingerprint = |manager: LibsqlConnectionManager| manager.build().unwrap().config_fingerprint();
+
+    assert_eq!(fingerprint(local()), fingerprint(local()));
+    assert_eq!(fingerprint(local().max_rows(10)), fingerprint(local().max_rows(10)));
+    let built = local().build().unwrap();
+    assert_eq!(built.config_fingerprint(), built.clone().config_fingerprint());
+    assert_eq!(fingerprint(replica(Duration::from_secs(1))), fingerprint(replica(Duration::from_secs(1))));
+
+    let base = fingerprint(local());
+    assert_ne!(base, fingerprint(LibsqlConnectionManager::local(dir.join("other.sqlite"))));
+    assert_ne!(base, fingerprint(LibsqlConnectionManager::local_replica(dir.join("db.sqlite"))));
+    assert_ne!(base, fingerprint(local().max_rows(10)));
+    assert_ne!(base, fingerprint(local().no_wal()));
+    std::fs::write(dir.join("ext.so"), "").unwrap();
+    assert_ne!(base, fingerprint(local().extensions([dir.join("ext.so")])));
+    assert_ne!(fingerprint(replica(Duration::from_secs(1))), fingerprint(replica(Duration::from_secs(2))));
+    assert_ne!(
+        fingerprint(LibsqlConnectionManager::remote(URL, TOKEN)),
+        fingerprint(LibsqlConnectionManager::remote("libsql://other.example.com", TOKEN)),
+    );
+    assert_ne!(
+        fingerprint(LibsqlConnectionManager::remote(URL, TOKEN)),
+        fingerprint(LibsqlConnectionManager::remote(URL, "another-token")),
+    );
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "connector")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bb8::ManageConnection;
+use bb8_libsql::LibsqlConnectionManager;
+use hyper::client::HttpConnector;
+
+/// An `HttpConnector` counting the connections made through all its clones.
+#[derive(Clone)]
+struct CountingConnector {
+    inner: HttpConnector,
+    connects: Arc<AtomicUsize>,
+}
+
+impl tower::Service<http::Uri> for CountingConnector {
+    type Response = <HttpConnector as tower::Service<http::Uri>>::Response;
+    type Error = <HttpConnector as tower::Service<http::Uri>>::Error;
+    type Future = <HttpConnector as tower::Service<http::Uri>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: http::Uri) -> Self::Future {
+        self.connects.fetch_add(1, Ordering::SeqCst);
+        self.inner.call(uri)
+    }
+}
+
+#[tokio::test]
+async fn managers_share_a_connector() {
+    let connects = Arc::new(AtomicUsize::new(0));
+    let connector = CountingConnector { inner: HttpConnector::new(), connects: connects.clone() };
+    let first = LibsqlConnectionManager::remote("http://127.0.0.1:1", "token").connector(connector.clone()).build().unwrap();
+    let second = LibsqlConnectionManager::remote("http://127.0.0.1:1", "token").connector(connector).build().unwrap();
+
+    let mut seen = 0;
+    for manager in [&first, &second] {
+        let conn = manager.connect().await.unwrap();
+        assert!(conn.execute("SELECT 1;", ()).await.is_err());
+        let now = connects.load(Ordering::SeqCst);
+        assert!(now > seen, "the manager did not connect through the shared connector");
+        seen = now;
+    }
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::{CorruptPolicy, LibsqlConnectionManager};
+
+use common::{TempDir, TOKEN, URL};
+
+// Nothing listens on port 1; with `manual_sync_only` the replica never
+// contacts it, so a discarded replica is recreated empty.
+const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+fn replica(path: &Path, policy: CorruptPolicy) -> LibsqlConnectionManager {
+    LibsqlConnectionManager::remote_replica(path, UNREACHABLE, "token", Duration::from_secs(3600))
+        .manual_sync_only()
+        .on_corrupt(policy)
+        .build()
+        .unwrap()
+}
+
+/// Creates a replica at `dir/replica.db` and then overwrites its database file
+/// with garbage.
+async fn corrupt_replica(dir: &TempDir) -> std::path::PathBuf {
+    let path = dir.join("replica.db");
+    let manager = replica(&path, CorruptPolicy::Fail);
+    manager.connect().await.unwrap();
+    manager.close().await.unwrap();
+    std::fs::write(&path, vec![7u8; 8192]).unwrap();
+    path
+}
+
+fn backups(dir: &TempDir) -> usize {
+    std::fs::read_dir(dir.path()).unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().contains(".corrupt-"))
+        .count()
+}
+
+#[tokio::test]
+async fn a_corrupt_replica_fails_by_default() {
+    let dir = TempDir::new();
+    let path = corrupt_replica(&dir).await;
+
+    let err = replica(&path, CorruptPolicy::Fail).connect().await.unwrap_err();
+    assert!(err.is_corrupt(), "{}", err);
+    assert_eq!(std::fs::read(&path).unwrap(), vec![7u8; 8192]);
+}
+
+#[tokio::test]
+async fn delete_and_resync_replaces_a_corrupt_replica() {
+    let dir = TempDir::new();
+    let path = corrupt_replica(&dir).await;
+
+    let conn = replica(&path, CorruptPolicy::DeleteAndResync).connect().await.unwrap();
+    conn.execute_batch("SELECT count(*) FROM sqlite_master;").await.unwrap();
+    assert_eq!(backups(&dir), 0);
+}
+
+#[tokio::test]
+async fn backup_moves_a_corrupt_replica_aside() {
+    let dir = TempDir::new();
+    let path = corrupt_replica(&dir).await;
+
+    let conn = replica(&path, CorruptPolicy::Backup).connect().await.unwrap();
+    conn.execute_batch("SELECT count(*) FROM sqlite_master;").await.unwrap();
+    assert!(backups(&dir) > 0);
+}
+
+#[test]
+fn on_corrupt_is_rejected_for_other_variants() {
+    let dir = TempDir::new();
+    let managers = [
+        LibsqlConnectionManager::local(dir.join("db.sqlite")),
+        LibsqlConnectionManager::remote(URL, TOKEN),
+        LibsqlConnectionManager::local_replica(dir.join("local-replica.db")),
+    ];
+    for manager in managers {
+        let err = manager.on_corrupt(CorruptPolicy::Backup).build().unwrap_err();
+        assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+    }
+}
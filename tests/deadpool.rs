@@ -0,0 +1,76 @@
+#![cfg(feature = "deadpool")]
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bb8_libsql::connection::LibsqlConnection;
+use bb8_libsql::deadpool::Pool;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, TempDir};
+
+#[tokio::test]
+async fn a_deadpool_pool_checks_out_and_recycles_connections() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let pool = Pool::builder(manager).max_size(1).build().unwrap();
+
+    let conn = pool.get().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);").await.unwrap();
+    drop(conn);
+
+    let conn = pool.get().await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1);
+    assert_eq!(pool.status().size, 1);
+}
+
+#[tokio::test]
+async fn a_connection_returned_in_a_transaction_is_not_reused() {
+    let dir = TempDir::new();
+    let leaks = Arc::new(AtomicUsize::new(0));
+    let counter = leaks.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_open_transaction(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    let pool = Pool::builder(manager).max_size(1).build().unwrap();
+
+    let conn = pool.get().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); BEGIN; INSERT INTO t VALUES (1);").await.unwrap();
+    drop(conn);
+
+    let conn = pool.get().await.unwrap();
+    assert!(conn.is_autocommit());
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 0);
+    assert_eq!(leaks.load(Ordering::SeqCst), 1);
+}
+
+/// The keepalive pings made so far on `conn`, from its `Debug` output.
+fn keepalive_pings(conn: &LibsqlConnection) -> u64 {
+    let debug = format!("{:?}", conn);
+    let pings = debug.split("keepalive_pings: Some(").nth(1).unwrap();
+    pings[..pings.find(')').unwrap()].parse().unwrap()
+}
+
+#[tokio::test]
+async fn keepalive_pings_connections_idle_in_a_deadpool_pool() {
+    let manager = LibsqlConnectionManager::remote("http://127.0.0.1:1", "token")
+        .keepalive_interval(Duration::from_millis(20))
+        // Keeps the unreachable connection through the checkout below.
+        .skip_checks(true)
+        .build()
+        .unwrap();
+    let pool = Pool::builder(manager).max_size(1).build().unwrap();
+    let conn = pool.get().await.unwrap();
+    let before = keepalive_pings(&conn);
+    drop(conn);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let conn = pool.get().await.unwrap();
+    assert!(keepalive_pings(&conn) > before);
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "encryption")]
+
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+use libsql::{Cipher, EncryptionConfig};
+
+use common::{scalar, server, TempDir, TOKEN, URL};
+
+fn key(key: &'static [u8]) -> EncryptionConfig {
+    EncryptionConfig::new(Cipher::Aes256Cbc, key.into())
+}
+
+async fn open_error(manager: LibsqlConnectionManager) -> ConnectionManagerError {
+    let manager = manager.build().unwrap();
+    match manager.connect().await {
+        Err(err) => err,
+        Ok(_) => panic!("expected the database to be rejected"),
+    }
+}
+
+async fn write_rows(path: &Path, encryption: Option<EncryptionConfig>) {
+    let mut manager = LibsqlConnectionManager::local(path);
+    if let Some(encryption) = encryption {
+        manager = manager.encryption(encryption);
+    }
+    let manager = manager.build().unwrap();
+    manager.connect().await.unwrap().execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);").await.unwrap();
+    manager.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_encrypted_local_database_can_be_reopened_with_its_key() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    write_rows(&path, Some(key(b"secret"))).await;
+
+    let manager = LibsqlConnectionManager::local(&path).encryption(key(b"secret")).build().unwrap();
+    assert_eq!(scalar(&manager.connect().await.unwrap(), "SELECT count(*) FROM t;").await, 1);
+    assert!(!std::fs::read(&path).unwrap().starts_with(b"SQLite format 3"));
+}
+
+#[tokio::test]
+async fn opening_with_the_wrong_key_fails_clearly() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    write_rows(&path, Some(key(b"secret"))).await;
+
+    let err = open_error(LibsqlConnectionManager::local(&path).encryption(key(b"other"))).await;
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn opening_an_unencrypted_file_with_a_key_fails_clearly() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    write_rows(&path, None).await;
+
+    let err = open_error(LibsqlConnectionManager::local(&path).encryption(key(b"secret"))).await;
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[test]
+fn encryption_is_rejected_for_remote_databases_and_empty_keys() {
+    let dir = TempDir::new();
+    let err = LibsqlConnectionManager::remote(URL, TOKEN).encryption(key(b"secret")).build().unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+    let err = LibsqlConnectionManager::local(dir.join("db.sqlite")).encryption(key(b"")).build().unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn an_encrypted_replica_file_can_be_reopened_with_its_key() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let path = dir.join("replica.db");
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    primary.connect().await.unwrap()
+        .execute_batch("DROP TABLE IF EXISTS encrypted; CREATE TABLE encrypted (x); INSERT INTO encrypted VALUES (1);")
+        .await
+        .unwrap();
+
+    let replica = |encryption| {
+        LibsqlConnectionManager::remote_replica(&path, &url, &token, Duration::from_secs(3600)).encryption(encryption)
+    };
+    let first = replica(key(b"secret")).build().unwrap();
+    first.connect().await.unwrap();
+    first.close().await.unwrap();
+
+    let reopened = replica(key(b"secret")).manual_sync_only().build().unwrap();
+    assert_eq!(scalar(&reopened.connect().await.unwrap(), "SELECT count(*) FROM encrypted;").await, 1);
+    reopened.close().await.unwrap();
+
+    let err = open_error(replica(key(b"other")).manual_sync_only()).await;
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
@@ -0,0 +1,60 @@
+//! `from_env` reads process-wide variables, so everything runs in one test.
+
+mod common;
+
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{TOKEN, URL};
+
+const VARS: &[&str] = &["LIBSQL_URL", "LIBSQL_TOKEN", "LIBSQL_SYNC_PATH", "LIBSQL_SYNC_INTERVAL", "LIBSQL_EXTENSIONS"];
+
+fn from_env(vars: &[(&str, &str)]) -> Result<String, ConnectionManagerError> {
+    for name in VARS {
+        std::env::remove_var(name);
+    }
+    for (name, value) in vars {
+        std::env::set_var(name, value);
+    }
+    LibsqlConnectionManager::from_env().map(|manager| format!("{:?}", manager))
+}
+
+fn missing(vars: &[(&str, &str)]) -> String {
+    match from_env(vars) {
+        Err(ConnectionManagerError::Config(message)) => message,
+        Err(err) => panic!("expected a Config error, got {}", err),
+        Ok(manager) => panic!("expected a Config error, got {}", manager),
+    }
+}
+
+#[test]
+fn from_env_picks_the_variant_from_the_set_variables() {
+    let remote = from_env(&[("LIBSQL_URL", URL), ("LIBSQL_TOKEN", TOKEN)]).unwrap();
+    assert!(remote.contains("source: Remote(\"libsql://db.example.com\""), "{}", remote);
+
+    let replica = from_env(&[
+        ("LIBSQL_URL", URL),
+        ("LIBSQL_TOKEN", TOKEN),
+        ("LIBSQL_SYNC_PATH", "replica.db"),
+        ("LIBSQL_SYNC_INTERVAL", "30"),
+    ]).unwrap();
+    assert!(replica.contains("source: RemoteReplica(\"replica.db\""), "{}", replica);
+    assert!(replica.contains("30s"), "{}", replica);
+
+    let extensions = std::env::join_paths(["a.so", "b.so"]).unwrap().into_string().unwrap();
+    let local = from_env(&[("LIBSQL_SYNC_PATH", "local.db"), ("LIBSQL_EXTENSIONS", &extensions)]).unwrap();
+    assert!(local.contains("source: Local(\"local.db\")"), "{}", local);
+    assert!(local.contains("extensions: [\"a.so\", \"b.so\"]"), "{}", local);
+
+    assert_eq!(missing(&[]), "missing environment variables: LIBSQL_URL or LIBSQL_SYNC_PATH");
+    assert_eq!(
+        missing(&[("LIBSQL_URL", URL), ("LIBSQL_SYNC_PATH", "replica.db")]),
+        "missing environment variables: LIBSQL_TOKEN, LIBSQL_SYNC_INTERVAL",
+    );
+    assert!(missing(&[
+        ("LIBSQL_URL", URL),
+        ("LIBSQL_TOKEN", TOKEN),
+        ("LIBSQL_SYNC_PATH", "replica.db"),
+        ("LIBSQL_SYNC_INTERVAL", "soon"),
+    ]).contains("not a number"));
+}
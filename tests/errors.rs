@@ -0,0 +1,134 @@
+mod common;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::TempDir;
+
+fn sqlite_failure(code: i32, message: &str) -> ConnectionManagerError {
+    libsql::Error::SqliteFailure(code, message.to_string()).into()
+}
+
+#[test]
+fn is_busy_matches_busy_and_locked_codes() {
+    assert!(sqlite_failure(5, "database is locked").is_busy());
+    assert!(sqlite_failure(6, "database table is locked").is_busy());
+    // Extended codes such as SQLITE_BUSY_SNAPSHOT classify by their primary code.
+    assert!(sqlite_failure(5 | (2 << 8), "snapshot").is_busy());
+    assert!(!sqlite_failure(1, "no such table: users").is_busy());
+    assert!(!ConnectionManagerError::Timeout.is_busy());
+}
+
+fn hrana(message: &str) -> ConnectionManagerError {
+    libsql::Error::Hrana(message.into()).into()
+}
+
+fn replication(message: &str) -> ConnectionManagerError {
+    libsql::Error::Replication(message.into()).into()
+}
+
+#[test]
+fn is_unauthorized_matches_rejected_credentials() {
+    assert!(hrana("api error: `Unauthorized`").is_unauthorized());
+    assert!(replication("status: Unauthenticated, message: \"grpc-status header missing, mapped from HTTP status code 401\"").is_unauthorized());
+    assert!(replication("status: PermissionDenied, message: \"token lacks access\"").is_unauthorized());
+    assert!(replication("status: Unknown, message: \"grpc-status header missing, mapped from HTTP status code 403\"").is_unauthorized());
+}
+
+#[test]
+fn is_unauthorized_does_not_match_sqlite_permissions() {
+    // SQLITE_AUTH: an authorizer denied the statement.
+    assert!(!sqlite_failure(23, "not authorized").is_unauthorized());
+    // SQLITE_PERM: the file could not be opened with the requested access.
+    assert!(!sqlite_failure(3, "access permission denied").is_unauthorized());
+    assert!(!hrana("SQLite error: permission denied for table t").is_unauthorized());
+    assert!(!sqlite_failure(1, "syntax error").is_unauthorized());
+}
+
+#[test]
+fn is_not_found_matches_missing_rows_and_namespaces() {
+    let no_rows: ConnectionManagerError = libsql::Error::QueryReturnedNoRows.into();
+    assert!(no_rows.is_not_found());
+    assert!(hrana("api error: `{\"error\":\"Namespace `tenant` doesn't exist\"}`").is_not_found());
+    assert!(replication("Requested namespace doesn't exist").is_not_found());
+    assert!(replication("status: NotFound, message: \"no such database\"").is_not_found());
+    assert!(replication("status: Unknown, message: \"grpc-status header missing, mapped from HTTP status code 404\"").is_not_found());
+}
+
+#[test]
+fn is_not_found_does_not_match_missing_schema_objects() {
+    assert!(!sqlite_failure(1, "no such table: users").is_not_found());
+    assert!(!hrana("SQLite error: no such table: users").is_not_found());
+    assert!(!hrana("SQLite error: no such column: name").is_not_found());
+    assert!(!hrana("SQLite error: function not found: f").is_not_found());
+    assert!(!sqlite_failure(5, "database is locked").is_not_found());
+}
+
+#[test]
+fn is_network_matches_transport_errors_only() {
+    let failed: ConnectionManagerError = libsql::Error::ConnectionFailed("connection refused".to_string()).into();
+    assert!(failed.is_network());
+    assert!(hrana("stream closed: `stream expired`").is_network());
+    assert!(hrana("http error: `error trying to connect: tcp connect error: Connection refused (os error 111)`").is_network());
+    assert!(replication("status: Unavailable, message: \"error trying to connect\"").is_network());
+    assert!(!hrana("SQL parse error").is_network());
+    assert!(!sqlite_failure(5, "database is locked").is_network());
+}
+
+#[test]
+fn is_network_does_not_match_database_errors_mentioning_connections() {
+    let local: ConnectionManagerError =
+        libsql::Error::ConnectionFailed("Unable to open connection to local database db.sqlite: 14".to_string()).into();
+    assert!(!local.is_network());
+    assert!(!hrana("SQLite error: no such table: connection").is_network());
+    assert!(!hrana("api error: `connection limit for namespace exceeded`").is_network());
+    assert!(!sqlite_failure(1, "cannot start a transaction within a transaction on this connection").is_network());
+}
+
+#[test]
+fn is_corrupt_and_is_disk_full_match_their_codes() {
+    assert!(sqlite_failure(26, "file is not a database").is_corrupt());
+    assert!(sqlite_failure(13, "database or disk is full").is_disk_full());
+    assert!(!sqlite_failure(13, "database or disk is full").is_corrupt());
+}
+
+#[test]
+fn classifiers_look_through_init_steps() {
+    let err = ConnectionManagerError::InitStep("seed".to_string(), Box::new(sqlite_failure(5, "database is locked")));
+    assert!(err.is_busy());
+}
+
+#[tokio::test]
+async fn a_real_lock_conflict_is_busy() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let first = manager.connect().await.unwrap();
+    let second = manager.connect().await.unwrap();
+    second.execute_batch("PRAGMA busy_timeout = 0;").await.unwrap();
+
+    first.execute_batch("BEGIN IMMEDIATE;").await.unwrap();
+    let err = second.execute_batch("BEGIN IMMEDIATE;").await.unwrap_err();
+    assert!(err.is_busy(), "{}", err);
+}
+
+#[tokio::test]
+async fn a_real_full_database_is_disk_full() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    // Capping the page count fails writes with SQLITE_FULL like a full disk.
+    conn.execute_batch("CREATE TABLE t (x); PRAGMA max_page_count = 4;").await.unwrap();
+
+    let err = conn.execute("INSERT INTO t VALUES (zeroblob(100000));", ()).await.unwrap_err();
+    assert!(err.is_disk_full(), "{}", err);
+    assert!(!err.is_busy());
+}
+
+#[test]
+fn as_libsql_returns_the_wrapped_error() {
+    let err: ConnectionManagerError = libsql::Error::QueryReturnedNoRows.into();
+    assert!(matches!(err.as_libsql(), Some(libsql::Error::QueryReturnedNoRows)));
+    assert!(ConnectionManagerError::Timeout.as_libsql().is_none());
+    assert!(ConnectionManagerError::Config("bad".to_string()).as_libsql().is_none());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::TempDir;
+
+/// Writes a file named like an extension that SQLite cannot load.
+fn bogus_extension(dir: &TempDir, name: &str) -> std::path::PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, b"not a shared library").unwrap();
+    path
+}
+
+#[tokio::test]
+async fn a_failing_extension_fails_the_connect() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions([bogus_extension(&dir, "bogus.so")])
+        .build()
+        .unwrap();
+    assert!(manager.connect().await.is_err());
+}
+
+#[tokio::test]
+async fn best_effort_skips_a_failing_extension() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions([bogus_extension(&dir, "bogus.so")])
+        .extensions_best_effort(true)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("SELECT 1;").await.unwrap();
+}
+
+#[test]
+fn a_missing_extension_is_rejected_unless_best_effort() {
+    let dir = TempDir::new();
+    let missing = dir.join("missing.so");
+    let err = LibsqlConnectionManager::local(dir.join("db.sqlite")).extensions([&missing]).build().unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+
+    LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions([&missing])
+        .extensions_best_effort(true)
+        .build()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn extensions_dir_loads_extensions_in_sorted_order() {
+    let dir = TempDir::new();
+    let extensions = TempDir::new();
+    bogus_extension(&extensions, "b.so");
+    bogus_extension(&extensions, "a.dylib");
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions_dir(extensions.path())
+        .build()
+        .unwrap();
+
+    // The first extension in sorted order is the one that fails the connect.
+    let err = manager.connect().await.unwrap_err();
+    assert!(err.to_string().contains("a.dylib"), "{}", err);
+}
+
+#[tokio::test]
+async fn extensions_dir_ignores_other_files() {
+    let dir = TempDir::new();
+    let extensions = TempDir::new();
+    bogus_extension(&extensions, "README.txt");
+    std::fs::create_dir(extensions.join("nested.so")).unwrap();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions_dir(extensions.path())
+        .build()
+        .unwrap();
+    manager.connect().await.unwrap();
+}
+
+#[tokio::test]
+async fn extensions_dir_respects_best_effort() {
+    let dir = TempDir::new();
+    let extensions = TempDir::new();
+    bogus_extension(&extensions, "a.so");
+    bogus_extension(&extensions, "b.so");
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions_dir(extensions.path())
+        .extensions_best_effort(true)
+        .build()
+        .unwrap();
+    manager.connect().await.unwrap();
+}
+
+#[cfg(feature = "extension_panic_guard")]
+#[tokio::test]
+async fn best_effort_skips_an_extension_whose_load_panics() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .extensions(["bogus\0.so"])
+        .extensions_best_effort(true)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("SELECT 1;").await.unwrap();
+}
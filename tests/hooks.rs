@@ -0,0 +1,116 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::TempDir;
+
+#[tokio::test]
+async fn on_first_connect_runs_once() {
+    let dir = TempDir::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_first_connect(move |_conn| {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .build()
+        .unwrap();
+
+    let pool = bb8::Pool::builder().max_size(3).min_idle(Some(3)).build(manager.clone()).await.unwrap();
+    let _conns = (pool.get().await.unwrap(), pool.get().await.unwrap(), pool.get().await.unwrap());
+    manager.clone().connect().await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_failed_on_first_connect_runs_again() {
+    let dir = TempDir::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_first_connect(move |_conn| {
+            let counter = counter.clone();
+            async move {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(ConnectionManagerError::Config("not yet".to_string())),
+                    _ => Ok(()),
+                }
+            }
+        })
+        .build()
+        .unwrap();
+
+    assert!(manager.connect().await.is_err());
+    manager.connect().await.unwrap();
+    manager.connect().await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn concurrent_connects_wait_for_on_first_connect() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_first_connect(|conn| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            conn.execute_batch("CREATE TABLE setup (x); INSERT INTO setup VALUES (1);").await?;
+            Ok(())
+        })
+        .build()
+        .unwrap();
+
+    let connects = (0..4).map(|_| {
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            let conn = manager.connect().await.unwrap();
+            let mut rows = conn.query("SELECT count(*) FROM setup;", ()).await.unwrap();
+            rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap()
+        })
+    });
+    for counted in futures::future::join_all(connects).await {
+        assert_eq!(counted.unwrap(), 1);
+    }
+}
+
+#[tokio::test]
+async fn init_steps_run_in_order_and_a_failure_names_the_step() {
+    let dir = TempDir::new();
+    let steps = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (first, second) = (steps.clone(), steps.clone());
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .init_step("schema", move |conn| {
+            first.lock().unwrap().push("schema");
+            async move {
+                conn.execute_batch("CREATE TABLE IF NOT EXISTS t (x);").await?;
+                Ok(())
+            }
+        })
+        .init_step("seed", move |conn| {
+            second.lock().unwrap().push("seed");
+            async move {
+                conn.execute_batch("INSERT INTO missing VALUES (1);").await?;
+                Ok(())
+            }
+        })
+        .build()
+        .unwrap();
+
+    match manager.connect().await {
+        Err(ConnectionManagerError::InitStep(name, err)) => {
+            assert_eq!(name, "seed");
+            assert!(err.to_string().contains("no such table"), "{}", err);
+        },
+        Err(err) => panic!("expected an InitStep error, got {}", err),
+        Ok(_) => panic!("expected the seed step to fail"),
+    }
+    assert_eq!(*steps.lock().unwrap(), ["schema", "seed"]);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::{LibsqlConnectionManager, MaintenanceOps};
+
+use common::{scalar, TempDir, TOKEN, URL};
+
+#[tokio::test]
+async fn maintenance_runs_every_operation_on_a_populated_database() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("
+        PRAGMA journal_mode = WAL;
+        CREATE TABLE t (x);
+        CREATE INDEX t_x ON t (x);
+        INSERT INTO t VALUES (1), (2), (3);
+    ").await.unwrap();
+
+    let ops = MaintenanceOps { vacuum: true, analyze: true, wal_checkpoint: true, optimize: true };
+    manager.maintenance(ops).await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM sqlite_master WHERE name = 'sqlite_stat1';").await, 1);
+}
+
+#[tokio::test]
+async fn maintenance_is_rejected_for_replicas() {
+    let dir = TempDir::new();
+    let replicas = [
+        LibsqlConnectionManager::local_replica(dir.join("local-replica.db")),
+        LibsqlConnectionManager::remote_replica(dir.join("remote-replica.db"), URL, TOKEN, Duration::from_secs(60)),
+    ];
+    for manager in replicas {
+        let ops = MaintenanceOps { analyze: true, ..MaintenanceOps::default() };
+        let err = manager.maintenance(ops).await.unwrap_err();
+        assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn auto_analyze_runs_on_its_interval() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .auto_analyze(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); CREATE INDEX t_x ON t (x); INSERT INTO t VALUES (1), (2), (3);")
+        .await
+        .unwrap();
+    let stats = "SELECT count(*) FROM sqlite_master WHERE name = 'sqlite_stat1';";
+    assert_eq!(scalar(&conn, stats).await, 0);
+
+    let mut waited = Duration::ZERO;
+    while scalar(&conn, stats).await == 0 {
+        assert!(waited < Duration::from_secs(5), "ANALYZE never ran");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        waited += Duration::from_millis(10);
+    }
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM sqlite_stat1 WHERE idx = 't_x';").await, 1);
+}
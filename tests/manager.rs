@@ -0,0 +1,416 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::{LibsqlConnectionManager, LockingMode, SecureDelete};
+
+use common::{non_local_managers, scalar, TempDir, TOKEN, URL};
+
+#[tokio::test]
+async fn checkpoint_on_close_truncates_the_wal() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let manager = LibsqlConnectionManager::local(&path)
+        .checkpoint_on_close(true)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE t (x); INSERT INTO t VALUES (1);").await.unwrap();
+
+    // SQLite removes the WAL when the last connection closes, so keep an
+    // idle one open, like a pool would.
+    let wal = dir.join("db.sqlite-wal");
+    assert!(std::fs::metadata(&wal).unwrap().len() > 0);
+    manager.close().await.unwrap();
+    assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+    drop(conn);
+}
+
+#[tokio::test]
+async fn checkpoint_on_close_truncates_the_wal_of_a_replica() {
+    let dir = TempDir::new();
+    let path = dir.join("replica.db");
+    let manager = LibsqlConnectionManager::remote_replica(&path, UNREACHABLE, TOKEN, Duration::from_secs(3600))
+        .manual_sync_only()
+        .checkpoint_on_close(true)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+
+    // Stands in for frames a sync would have written to the replica's WAL.
+    let writer = libsql::Builder::new_local(&path).build().await.unwrap().connect().unwrap();
+    writer.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);").await.unwrap();
+    let wal = dir.join("replica.db-wal");
+    assert!(std::fs::metadata(&wal).unwrap().len() > 0);
+
+    drop(conn);
+    manager.close().await.unwrap();
+    assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+    drop(writer);
+}
+
+#[test]
+fn checkpoint_on_close_is_rejected_for_remote_databases() {
+    let err = LibsqlConnectionManager::remote(URL, TOKEN).checkpoint_on_close(true).build().unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn local_only_options_are_rejected_for_remote_databases_and_replicas() {
+    type Set = fn(LibsqlConnectionManager) -> LibsqlConnectionManager;
+    let options: [(&str, Set); 6] = [
+        ("secure_delete", |m| m.secure_delete(SecureDelete::Fast)),
+        ("locking_mode", |m| m.locking_mode(LockingMode::Normal)),
+        ("cache_spill", |m| m.cache_spill(false)),
+        ("temp_dir", |m| m.temp_dir(std::env::temp_dir())),
+        ("no_wal", |m| m.no_wal()),
+        ("wal_index_in_memory", |m| m.wal_index_in_memory(true)),
+    ];
+    let dir = TempDir::new();
+    for (name, option) in options {
+        for manager in non_local_managers(&dir) {
+            let err = option(manager).build().unwrap_err();
+            assert!(matches!(err, ConnectionManagerError::Config(_)), "{}: {}", name, err);
+        }
+    }
+    for manager in non_local_managers(&dir) {
+        let err = manager.snapshot_into(dir.join("snapshot.sqlite")).await.unwrap_err();
+        assert!(matches!(err, ConnectionManagerError::Config(_)), "snapshot_into: {}", err);
+    }
+}
+
+// Nothing listens on port 1, so any request to it fails right away.
+const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+#[tokio::test]
+async fn validate_on_create_fails_for_an_unreachable_server() {
+    let manager = LibsqlConnectionManager::remote(UNREACHABLE, "token").validate_on_create(true).build().unwrap();
+    assert!(manager.connect().await.is_err());
+}
+
+#[tokio::test]
+async fn without_validate_on_create_remote_connects_are_lazy() {
+    let manager = LibsqlConnectionManager::remote(UNREACHABLE, "token").build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    assert!(conn.execute_batch("SELECT 1;").await.is_err());
+}
+
+#[tokio::test]
+async fn skip_checks_skips_validation_on_connect_and_checkout() {
+    let manager = LibsqlConnectionManager::remote(UNREACHABLE, "token")
+        .validate_on_create(true)
+        .skip_checks(true)
+        .build()
+        .unwrap();
+
+    let mut conn = manager.connect().await.unwrap();
+    manager.is_valid(&mut conn).await.unwrap();
+}
+
+#[tokio::test]
+async fn connections_share_one_database() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let builds = Arc::new(AtomicUsize::new(0));
+    let counter = builds.clone();
+    let manager = LibsqlConnectionManager::remote_with(URL, TOKEN, move |_url, _token| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let path = path.clone();
+        async move { libsql::Builder::new_local(path).build().await }
+    })
+    .build()
+    .unwrap();
+
+    let pool = bb8::Pool::builder().max_size(3).min_idle(Some(3)).build(manager.clone()).await.unwrap();
+    let _conns = (pool.get().await.unwrap(), pool.get().await.unwrap(), pool.get().await.unwrap());
+    manager.connect().await.unwrap();
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn sync_fails_for_a_local_database() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    assert!(manager.sync().await.is_err());
+}
+
+#[tokio::test]
+async fn sync_with_timeout_fails_within_the_bound_for_an_unreachable_remote() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), UNREACHABLE, TOKEN, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    assert!(manager.sync_with_timeout(Duration::from_secs(2)).await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn clones_share_state() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let clone = manager.clone();
+
+    clone.close().await.unwrap();
+    assert!(matches!(manager.connect().await, Err(ConnectionManagerError::Closed)));
+}
+
+#[tokio::test]
+async fn a_reconfigured_clone_gets_its_own_state() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    manager.connect().await.unwrap();
+
+    let first_connects = Arc::new(AtomicUsize::new(0));
+    let counter = first_connects.clone();
+    let reconfigured = manager
+        .clone()
+        .on_first_connect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        })
+        .build()
+        .unwrap();
+    reconfigured.connect().await.unwrap();
+    reconfigured.connect().await.unwrap();
+    assert_eq!(first_connects.load(Ordering::SeqCst), 1);
+
+    manager.close().await.unwrap();
+    reconfigured.connect().await.unwrap();
+    reconfigured.close().await.unwrap();
+    assert!(matches!(reconfigured.connect().await, Err(ConnectionManagerError::Closed)));
+}
+
+#[tokio::test]
+async fn manual_sync_only_replicas_connect_without_syncing() {
+    let dir = TempDir::new();
+    let syncs = Arc::new(AtomicUsize::new(0));
+    let counter = syncs.clone();
+    let manager = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), UNREACHABLE, TOKEN, Duration::from_millis(10))
+        .manual_sync_only()
+        .sync_progress(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    manager.connect().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(syncs.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn replica_lag_is_none_for_non_replicas_and_unsynced_replicas() {
+    let dir = TempDir::new();
+    let local = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    assert_eq!(local.replica_lag().await.unwrap(), None);
+
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), UNREACHABLE, TOKEN, Duration::from_secs(3600))
+        .manual_sync_only()
+        .build()
+        .unwrap();
+    assert_eq!(replica.replica_lag().await.unwrap(), None);
+}
+
+/// Counts the rows a second connection sees while the first holds an
+/// uncommitted insert.
+async fn rows_seen_during_uncommitted_insert(shared_cache: bool) -> i64 {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).shared_cache(shared_cache).build().unwrap();
+    let writer = manager.connect().await.unwrap();
+    let reader = manager.connect().await.unwrap();
+    writer.execute_batch("CREATE TABLE t (x); BEGIN; INSERT INTO t VALUES (1);").await.unwrap();
+
+    reader.execute_batch("PRAGMA read_uncommitted = 1;").await.unwrap();
+    scalar(&reader, "SELECT count(*) FROM t;").await
+}
+
+#[tokio::test]
+async fn shared_cache_connections_share_one_cache() {
+    // `read_uncommitted` only sees other connections' writes through a shared cache.
+    assert_eq!(rows_seen_during_uncommitted_insert(true).await, 1);
+    assert_eq!(rows_seen_during_uncommitted_insert(false).await, 0);
+}
+
+#[tokio::test]
+async fn remote_with_builds_the_database_with_the_url_and_token_until_it_succeeds() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = calls.clone();
+    let manager = LibsqlConnectionManager::remote_with(URL, TOKEN, move |url, token| {
+        let mut seen = seen.lock().unwrap();
+        seen.push((url, token));
+        let first = seen.len() == 1;
+        let path = path.clone();
+        async move {
+            if first {
+                return Err(libsql::Error::ConnectionFailed("refused".to_string()));
+            }
+            libsql::Builder::new_local(path).build().await
+        }
+    })
+    .build()
+    .unwrap();
+
+    assert!(manager.connect().await.is_err());
+    manager.connect().await.unwrap();
+    manager.connect().await.unwrap();
+    let expected = (URL.to_string(), TOKEN.to_string());
+    assert_eq!(*calls.lock().unwrap(), [expected.clone(), expected]);
+}
+
+#[tokio::test]
+async fn the_sync_task_reports_the_failure_streak() {
+    let dir = TempDir::new();
+    let streaks = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = streaks.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_sync_failure(move |failures, _err| seen.lock().unwrap().push(failures))
+        .build()
+        .unwrap();
+
+    // Syncing a local database always fails.
+    let task = manager.spawn_sync_task(Duration::from_millis(10));
+    while streaks.lock().unwrap().len() < 3 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    task.abort();
+    assert_eq!(streaks.lock().unwrap()[..3], [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn tenant_replicas_get_their_own_files() {
+    let dir = TempDir::new();
+    for tenant in ["acme", "globex"] {
+        let manager = LibsqlConnectionManager::remote_replica_for_tenant(dir.path(), tenant, UNREACHABLE, TOKEN, Duration::from_secs(3600))
+            .manual_sync_only()
+            .build()
+            .unwrap();
+        manager.connect().await.unwrap();
+    }
+
+    assert!(dir.join("acme.db").is_file());
+    assert!(dir.join("globex.db").is_file());
+}
+
+#[test]
+fn tenant_ids_are_validated() {
+    let dir = TempDir::new();
+    for tenant in ["", "../escape", "a b"] {
+        let manager = LibsqlConnectionManager::remote_replica_for_tenant(dir.path(), tenant, URL, TOKEN, Duration::from_secs(1));
+        assert!(matches!(manager.build(), Err(ConnectionManagerError::Config(_))), "{:?}", tenant);
+    }
+}
+
+#[tokio::test]
+async fn connect_after_close_fails_with_closed() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let open = manager.connect().await.unwrap();
+
+    manager.close().await.unwrap();
+    assert!(matches!(manager.connect().await, Err(ConnectionManagerError::Closed)));
+    // Connections opened before the close keep working until dropped.
+    open.execute_batch("SELECT 1;").await.unwrap();
+}
+
+#[test]
+fn the_bundled_sqlite_is_threadsafe() {
+    LibsqlConnectionManager::verify_threadsafe().unwrap();
+}
+
+#[tokio::test]
+async fn new_test_connections_share_one_in_memory_database() {
+    let manager = LibsqlConnectionManager::new_test().build().unwrap();
+    let pool = bb8::Pool::builder().max_size(2).build(manager).await.unwrap();
+    let (first, second) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+
+    first.execute_batch("CREATE TABLE parents (id INTEGER PRIMARY KEY); INSERT INTO parents VALUES (1);").await.unwrap();
+    assert_eq!(scalar(&second, "SELECT count(*) FROM parents;").await, 1);
+    assert_eq!(scalar(&second, "PRAGMA foreign_keys;").await, 1);
+
+    // Each test manager gets its own database.
+    let other = LibsqlConnectionManager::new_test().build().unwrap().connect().await.unwrap();
+    assert!(other.execute_batch("SELECT * FROM parents;").await.is_err());
+}
+
+#[tokio::test]
+async fn the_sync_task_backs_off_after_failures() {
+    let dir = TempDir::new();
+    let failed_at = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = failed_at.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .sync_backoff_cap(Duration::from_millis(160))
+        .on_sync_failure(move |_, _| seen.lock().unwrap().push(Instant::now()))
+        .build()
+        .unwrap();
+
+    let task = manager.spawn_sync_task(Duration::from_millis(20));
+    while failed_at.lock().unwrap().len() < 5 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    task.abort();
+
+    // 40ms, 80ms, then capped at 160ms.
+    let failed_at = failed_at.lock().unwrap();
+    let gaps = failed_at.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>();
+    assert!(gaps[0] >= Duration::from_millis(40) && gaps[0] < Duration::from_millis(80), "{:?}", gaps);
+    assert!(gaps[3] >= Duration::from_millis(160) && gaps[3] < Duration::from_millis(320), "{:?}", gaps);
+}
+
+/// Listens on a free local port, closing every accepted socket, and returns
+/// its url with the number of connections it accepted.
+fn closing_listener() -> (String, Arc<AtomicUsize>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            drop(socket);
+        }
+    });
+    (url, accepted)
+}
+
+#[tokio::test]
+async fn url_resolver_is_asked_for_the_url_on_each_connect() {
+    let (first_url, first) = closing_listener();
+    let (second_url, second) = closing_listener();
+    let resolves = Arc::new(AtomicUsize::new(0));
+    let counter = resolves.clone();
+    let manager = LibsqlConnectionManager::remote(UNREACHABLE, TOKEN)
+        .url_resolver(move || {
+            let url = if counter.fetch_add(1, Ordering::SeqCst) == 0 { first_url.clone() } else { second_url.clone() };
+            async move { Ok(url) }
+        })
+        .build()
+        .unwrap();
+
+    let conn = manager.connect().await.unwrap();
+    assert!(conn.execute("SELECT 1;", ()).await.is_err());
+    assert!(first.load(Ordering::SeqCst) > 0);
+    assert_eq!(second.load(Ordering::SeqCst), 0);
+
+    let conn = manager.connect().await.unwrap();
+    assert!(conn.execute("SELECT 1;", ()).await.is_err());
+    assert!(second.load(Ordering::SeqCst) > 0);
+    assert_eq!(resolves.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn a_failing_url_resolver_fails_the_connect() {
+    let manager = LibsqlConnectionManager::remote(URL, TOKEN)
+        .url_resolver(|| async { Err(ConnectionManagerError::Config("no endpoint".to_string())) })
+        .build()
+        .unwrap();
+    let err = manager.connect().await.unwrap_err();
+    assert!(err.to_string().contains("no endpoint"), "{}", err);
+}
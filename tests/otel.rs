@@ -0,0 +1,90 @@
+#![cfg(feature = "otel")]
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bb8::ManageConnection;
+use bb8_libsql::{query, LibsqlConnectionManager};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use common::TempDir;
+
+/// Collects the `db.statement` of every `libsql.query` span.
+#[derive(Default)]
+struct Statements {
+    next_id: AtomicU64,
+    query_spans: Mutex<HashSet<u64>>,
+    statements: Arc<Mutex<Vec<String>>>,
+}
+
+struct StatementVisitor<'a>(&'a Mutex<Vec<String>>);
+
+impl Visit for StatementVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "db.statement" {
+            self.0.lock().unwrap().push(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+impl Subscriber for Statements {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        if span.metadata().name() == "libsql.query" {
+            self.query_spans.lock().unwrap().insert(id);
+        }
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if self.query_spans.lock().unwrap().contains(&span.into_u64()) {
+            values.record(&mut StatementVisitor(&self.statements));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[tokio::test]
+async fn query_spans_cover_wrapper_and_helper_statements() {
+    let subscriber = Statements::default();
+    let statements = subscriber.statements.clone();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x);").await.unwrap();
+    conn.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+    query::execute(&conn, "INSERT INTO t VALUES (2);", ()).await.unwrap();
+    query::insert_many(&conn, "INSERT INTO t VALUES (?1);", [[3]]).await.unwrap();
+    query::query_scalar::<i64>(&conn, "SELECT count(*) FROM t;", ()).await.unwrap();
+
+    let statements = statements.lock().unwrap();
+    for sql in [
+        "CREATE TABLE t (x);",
+        "INSERT INTO t VALUES (1);",
+        "INSERT INTO t VALUES (2);",
+        "INSERT INTO t VALUES (?1);",
+        "SELECT count(*) FROM t;",
+    ] {
+        assert!(statements.iter().any(|statement| statement == sql), "no span for {}: {:?}", sql, statements);
+    }
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bb8::{Pool, RunError};
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::pool::{
+    build_and_warm, build_and_warm_with, utilization, wait_ready, ColumnInfo, DrainablePool, PinnedConnections, PoolExt, ReadWriteSplit, TimedPool,
+};
+use bb8_libsql::{LibsqlConnectionManager, LockingMode};
+
+use common::{TempDir, TOKEN, URL};
+
+async fn pool(dir: &TempDir, max_size: u32) -> Pool<LibsqlConnectionManager> {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    Pool::builder().max_size(max_size).build(manager).await.unwrap()
+}
+
+#[tokio::test]
+async fn timed_pool_records_waits_under_contention() {
+    let dir = TempDir::new();
+    let timed = TimedPool::new(pool(&dir, 1).await);
+
+    let held = timed.get().await.unwrap();
+    let waiter = {
+        let timed = timed.clone();
+        tokio::spawn(async move {
+            timed.get().await.unwrap();
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(held);
+    waiter.await.unwrap();
+
+    let wait_times = timed.wait_times();
+    assert_eq!(wait_times.count(), 2);
+    assert!(wait_times.total() >= Duration::from_millis(100), "{:?}", wait_times.total());
+    let slow: u64 = wait_times.buckets().iter()
+        .filter(|(bound, _)| bound.is_none_or(|bound| bound >= Duration::from_millis(100)))
+        .map(|(_, count)| count)
+        .sum();
+    assert_eq!(slow, 1);
+}
+
+#[tokio::test]
+async fn table_exists_and_columns_describe_the_schema() {
+    let dir = TempDir::new();
+    let pool = pool(&dir, 1).await;
+    pool.get().await.unwrap()
+        .execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'anon');")
+        .await
+        .unwrap();
+
+    assert!(pool.table_exists("users").await.unwrap());
+    assert!(!pool.table_exists("missing").await.unwrap());
+    assert_eq!(pool.columns("users").await.unwrap(), [
+        ColumnInfo {
+            cid: 0,
+            name: "id".to_string(),
+            decl_type: "INTEGER".to_string(),
+            not_null: false,
+            default_value: None,
+            primary_key: 1,
+        },
+        ColumnInfo {
+            cid: 1,
+            name: "name".to_string(),
+            decl_type: "TEXT".to_string(),
+            not_null: true,
+            default_value: Some("'anon'".to_string()),
+            primary_key: 0,
+        },
+    ]);
+    assert!(pool.columns("missing").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn pinned_connections_reuse_one_connection_per_key() {
+    let dir = TempDir::new();
+    let pool = pool(&dir, 2).await;
+    let mut pinned = PinnedConnections::new(&pool);
+
+    // A temp table is only visible on the connection that created it.
+    pinned.get_pinned("a").await.unwrap().execute_batch("CREATE TEMP TABLE mine (x);").await.unwrap();
+    pinned.get_pinned("a").await.unwrap().execute_batch("SELECT * FROM mine;").await.unwrap();
+    assert!(pinned.get_pinned("b").await.unwrap().execute_batch("SELECT * FROM mine;").await.is_err());
+    assert_eq!(pool.state().idle_connections, 0);
+
+    assert!(pinned.release(&"a"));
+    assert!(!pinned.release(&"a"));
+    assert_eq!(pool.state().idle_connections, 1);
+    drop(pinned);
+    assert_eq!(pool.state().idle_connections, 2);
+}
+
+#[tokio::test]
+async fn healthy_reports_a_working_pool() {
+    let dir = TempDir::new();
+    assert!(pool(&dir, 1).await.healthy().await);
+}
+
+#[tokio::test]
+async fn healthy_reports_an_unreachable_remote_within_the_timeout() {
+    let manager = LibsqlConnectionManager::remote("http://127.0.0.1:1", "token").build().unwrap();
+    let pool = Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    let started = Instant::now();
+    assert!(!pool.healthy().await);
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn healthy_reports_a_saturated_pool_without_waiting_for_it() {
+    let dir = TempDir::new();
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(30))
+        .build(LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap())
+        .await
+        .unwrap();
+    let _held = pool.get().await.unwrap();
+
+    let started = Instant::now();
+    assert!(!pool.healthy().await);
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn read_write_split_rejects_the_wrong_kinds_of_database() {
+    let dir = TempDir::new();
+    let local = || LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let replica = || {
+        LibsqlConnectionManager::remote_replica(dir.join("replica.db"), URL, TOKEN, Duration::from_secs(3600))
+            .build()
+            .unwrap()
+    };
+    let primary = || LibsqlConnectionManager::remote(URL, TOKEN).build().unwrap();
+
+    let err = ReadWriteSplit::new(local(), primary()).await.unwrap_err();
+    assert!(err.to_string().contains("read side"), "{}", err);
+    let err = ReadWriteSplit::new(replica(), local()).await.unwrap_err();
+    assert!(err.to_string().contains("write side"), "{}", err);
+    let err = ReadWriteSplit::new(replica(), replica()).await.unwrap_err();
+    assert!(err.to_string().contains("write side"), "{}", err);
+}
+
+#[tokio::test]
+async fn build_and_warm_opens_every_connection() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let pool = build_and_warm(manager.clone(), 3).await.unwrap();
+    let state = pool.state();
+    assert_eq!((state.connections, state.idle_connections), (3, 3));
+
+    let err = build_and_warm(manager, 0).await.unwrap_err();
+    assert!(err.to_string().contains("at least one"), "{}", err);
+}
+
+#[tokio::test]
+async fn build_and_warm_rejects_a_larger_pool_for_wal_index_in_memory() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .wal_index_in_memory(true)
+        .locking_mode(LockingMode::Exclusive)
+        .build()
+        .unwrap();
+    let err = build_and_warm(manager.clone(), 2).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+    build_and_warm(manager, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn build_and_warm_fails_for_an_unreachable_remote() {
+    let manager = LibsqlConnectionManager::remote("http://127.0.0.1:1", TOKEN).build().unwrap();
+    let builder = Pool::builder().connection_timeout(Duration::from_millis(500));
+    let started = Instant::now();
+    assert!(build_and_warm_with(builder, manager, 2).await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn drain_rejects_checkouts_until_the_maintenance_is_done() {
+    let dir = TempDir::new();
+    let drainable = DrainablePool::new(pool(&dir, 2).await);
+    let held = drainable.get().await.unwrap();
+
+    let drain = {
+        let drainable = drainable.clone();
+        tokio::spawn(async move {
+            drainable.drain(|conn| async move { conn.execute_batch("CREATE TABLE migrated (x);").await }).await
+        })
+    };
+    while !drainable.is_draining() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(matches!(drainable.get().await, Err(RunError::User(ConnectionManagerError::Draining))));
+
+    // The drain waits for the held connection before running.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!drain.is_finished());
+    drop(held);
+    drain.await.unwrap().unwrap();
+
+    assert!(!drainable.is_draining());
+    let conn = drainable.get().await.unwrap();
+    conn.execute_batch("INSERT INTO migrated VALUES (1);").await.unwrap();
+}
+
+#[tokio::test]
+async fn drain_waits_for_connections_taken_from_the_wrapped_pool() {
+    let dir = TempDir::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let manager = {
+        let log = log.clone();
+        LibsqlConnectionManager::local(dir.join("db.sqlite"))
+            .query_logger(move |sql, _elapsed| log.lock().unwrap().push(sql.to_string()))
+            .build()
+            .unwrap()
+    };
+    let drainable = DrainablePool::new(Pool::builder().max_size(2).build(manager).await.unwrap());
+    let held = drainable.pool().get().await.unwrap();
+
+    let drain = {
+        let drainable = drainable.clone();
+        tokio::spawn(async move {
+            drainable.drain(|conn| async move { conn.execute_batch("CREATE TABLE migrated (x);").await }).await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!drain.is_finished());
+    drop(held);
+    drain.await.unwrap().unwrap();
+
+    // `f` runs on the manager's connection wrapper, so its hooks apply.
+    assert!(log.lock().unwrap().iter().any(|sql| sql == "CREATE TABLE migrated (x);"));
+}
+
+#[tokio::test]
+async fn a_failed_drain_resumes_checkouts() {
+    let dir = TempDir::new();
+    let drainable = DrainablePool::new(pool(&dir, 1).await);
+    let err = drainable.drain(|_| async { Err::<(), _>(ConnectionManagerError::Config("failed".to_string())) }).await.unwrap_err();
+    assert!(err.to_string().contains("failed"), "{}", err);
+    assert!(!drainable.is_draining());
+    drainable.get().await.unwrap();
+}
+
+#[tokio::test]
+async fn utilization_counts_checked_out_connections() {
+    let dir = TempDir::new();
+    let pool = pool(&dir, 4).await;
+    let idle = utilization(&pool, 4);
+    assert_eq!(idle.in_use, 0);
+    assert_eq!(idle.ratio(), 0.0);
+
+    let held = vec![pool.get().await.unwrap(), pool.get().await.unwrap(), pool.get().await.unwrap()];
+    let busy = utilization(&pool, 4);
+    assert_eq!((busy.in_use, busy.max), (3, 4));
+    assert_eq!(busy.in_use + busy.idle, pool.state().connections);
+    assert_eq!(busy.ratio(), 0.75);
+
+    drop(held);
+    let returned = utilization(&pool, 4);
+    assert_eq!(returned.in_use, 0);
+    assert_eq!(returned.idle, pool.state().connections);
+}
+
+/// A pool over `path` whose failed connects are reported after 100ms.
+fn impatient_pool(path: std::path::PathBuf) -> Pool<LibsqlConnectionManager> {
+    let manager = LibsqlConnectionManager::local(path).build().unwrap();
+    Pool::builder().connection_timeout(Duration::from_millis(100)).build_unchecked(manager)
+}
+
+#[tokio::test]
+async fn wait_ready_succeeds_once_the_database_becomes_available() {
+    let dir = TempDir::new();
+    // A directory in place of the database fails every connect until removed.
+    let path = dir.join("db.sqlite");
+    std::fs::create_dir(&path).unwrap();
+    let pool = impatient_pool(path.clone());
+    assert!(pool.get().await.is_err());
+
+    let available = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        std::fs::remove_dir(&path).unwrap();
+    });
+    let started = Instant::now();
+    wait_ready(&pool, Duration::from_secs(10)).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    available.await.unwrap();
+}
+
+#[tokio::test]
+async fn wait_ready_gives_up_at_the_deadline() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    std::fs::create_dir(&path).unwrap();
+    let pool = impatient_pool(path);
+
+    let started = Instant::now();
+    let err = wait_ready(&pool, Duration::from_millis(500)).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Timeout), "{}", err);
+    assert!(started.elapsed() >= Duration::from_millis(500));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
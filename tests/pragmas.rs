@@ -0,0 +1,106 @@
+mod common;
+
+use bb8::ManageConnection;
+use bb8_libsql::{LibsqlConnectionManager, LockingMode, SecureDelete};
+
+use common::{scalar, TempDir};
+
+#[tokio::test]
+async fn secure_delete_is_applied_to_local_connections() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .secure_delete(SecureDelete::On)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    assert_eq!(scalar(&conn, "PRAGMA secure_delete;").await, 1);
+}
+
+#[tokio::test]
+async fn locking_mode_is_applied_to_local_connections() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .locking_mode(LockingMode::Exclusive)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    let mut rows = conn.query("PRAGMA locking_mode;", ()).await.unwrap();
+    let mode = rows.next().await.unwrap().unwrap().get::<String>(0).unwrap();
+    assert_eq!(mode, "exclusive");
+}
+
+#[tokio::test]
+async fn cache_spill_is_applied_to_local_connections() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .cache_spill(false)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    assert_eq!(scalar(&conn, "PRAGMA cache_spill;").await, 0);
+}
+
+#[tokio::test]
+async fn temp_dir_is_applied_to_local_connections() {
+    let dir = TempDir::new();
+    let temp = dir.join("tmp");
+    std::fs::create_dir(&temp).unwrap();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .temp_dir(&temp)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    let mut rows = conn.query("PRAGMA temp_store_directory;", ()).await.unwrap();
+    let applied = rows.next().await.unwrap().unwrap().get::<String>(0).unwrap();
+    // The setting is process-wide, so restore the default for other tests.
+    conn.execute_batch("PRAGMA temp_store_directory = '';").await.unwrap();
+    assert_eq!(applied, temp.to_str().unwrap());
+}
+
+#[tokio::test]
+async fn no_wal_switches_a_wal_database_to_a_rollback_journal() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let wal = LibsqlConnectionManager::local(&path).build().unwrap();
+    wal.connect().await.unwrap().execute_batch("PRAGMA journal_mode = WAL;").await.unwrap();
+    wal.close().await.unwrap();
+
+    let manager = LibsqlConnectionManager::local(&path).no_wal().build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    let mut rows = conn.query("PRAGMA journal_mode;", ()).await.unwrap();
+    let mode = rows.next().await.unwrap().unwrap().get::<String>(0).unwrap();
+    assert_eq!(mode, "delete");
+}
+
+#[tokio::test]
+async fn wal_index_in_memory_opens_a_wal_database_without_a_shm_file() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .wal_index_in_memory(true)
+        .locking_mode(LockingMode::Exclusive)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE t (x); INSERT INTO t VALUES (1);").await.unwrap();
+
+    let mut rows = conn.query("PRAGMA locking_mode;", ()).await.unwrap();
+    assert_eq!(rows.next().await.unwrap().unwrap().get::<String>(0).unwrap(), "exclusive");
+    let mut rows = conn.query("PRAGMA journal_mode;", ()).await.unwrap();
+    assert_eq!(rows.next().await.unwrap().unwrap().get::<String>(0).unwrap(), "wal");
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1);
+    assert!(dir.join("db.sqlite-wal").exists());
+    assert!(!dir.join("db.sqlite-shm").exists());
+}
+
+#[test]
+fn wal_index_in_memory_is_rejected_without_exclusive_locking() {
+    let dir = TempDir::new();
+    for locking_mode in [None, Some(LockingMode::Normal)] {
+        let mut manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).wal_index_in_memory(true);
+        if let Some(mode) = locking_mode {
+            manager = manager.locking_mode(mode);
+        }
+        let err = manager.build().unwrap_err();
+        assert!(err.to_string().contains("locking_mode(LockingMode::Exclusive)"), "{}", err);
+    }
+}
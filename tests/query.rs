@@ -0,0 +1,415 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::row::{column, FromLibsqlRow};
+use bb8_libsql::{params, query, LibsqlConnectionManager};
+use futures::{StreamExt, TryStreamExt};
+
+use common::TempDir;
+
+fn logged_manager(dir: &TempDir) -> (LibsqlConnectionManager, Arc<Mutex<Vec<String>>>) {
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let log = logged.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .query_logger(move |sql, _elapsed| log.lock().unwrap().push(sql.to_string()))
+        .build()
+        .unwrap();
+    (manager, logged)
+}
+
+#[tokio::test]
+async fn the_query_logger_sees_wrapper_and_helper_statements() {
+    let dir = TempDir::new();
+    let (manager, logged) = logged_manager(&dir);
+    let conn = manager.connect().await.unwrap();
+
+    conn.execute_batch("CREATE TABLE t (x);").await.unwrap();
+    conn.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+    query::execute(&conn, "INSERT INTO t VALUES (2);", ()).await.unwrap();
+    query::query_scalar::<i64>(&conn, "SELECT count(*) FROM t;", ()).await.unwrap();
+
+    assert_eq!(*logged.lock().unwrap(), [
+        "CREATE TABLE t (x);",
+        "INSERT INTO t VALUES (1);",
+        "INSERT INTO t VALUES (2);",
+        "SELECT count(*) FROM t;",
+    ]);
+}
+
+#[tokio::test]
+async fn the_query_logger_sees_transactions_and_batches() {
+    let dir = TempDir::new();
+    let (manager, logged) = logged_manager(&dir);
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x);").await.unwrap();
+    logged.lock().unwrap().clear();
+
+    query::insert_many(&conn, "INSERT INTO t VALUES (?1);", [[1], [2]]).await.unwrap();
+    let tx = query::begin(&conn, query::TransactionBehavior::Immediate).await.unwrap();
+    tx.execute("DELETE FROM t;", ()).await.unwrap();
+    tx.commit().await.unwrap();
+    query::transaction_retry(&conn, 0, |conn| async move {
+        conn.execute("INSERT INTO t VALUES (3);", ()).await?;
+        Ok(())
+    }).await.unwrap();
+
+    assert_eq!(*logged.lock().unwrap(), [
+        "INSERT INTO t VALUES (?1);",
+        "INSERT INTO t VALUES (?1);",
+        "DELETE FROM t;",
+        "INSERT INTO t VALUES (3);",
+    ]);
+}
+
+async fn limited(dir: &TempDir, truncate: bool) -> bb8_libsql::connection::LibsqlConnection {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .max_rows(2)
+        .truncate_at_max_rows(truncate)
+        .build()
+        .unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1), (2), (3);").await.unwrap();
+    conn
+}
+
+#[tokio::test]
+async fn max_rows_fails_every_row_helper() {
+    let dir = TempDir::new();
+    let conn = limited(&dir, false).await;
+    let sql = "SELECT x FROM t ORDER BY x;";
+
+    let err = query::query_map(&conn, sql, (), |row| Ok(row.get::<i64>(0)?)).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::TooManyRows(2)), "{}", err);
+
+    let rows: Vec<_> = query::query_stream(&conn, sql, ()).await.unwrap().collect().await;
+    assert_eq!(rows.len(), 3);
+    assert!(matches!(rows[2], Err(ConnectionManagerError::TooManyRows(2))));
+
+    let rows: Vec<_> = conn.query_stream(sql, ()).await.unwrap().collect().await;
+    assert!(matches!(rows[2], Err(ConnectionManagerError::TooManyRows(2))));
+
+    // Exactly `max_rows` rows is within the limit.
+    let values = query::query_map(&conn, "SELECT x FROM t LIMIT 2;", (), |row| Ok(row.get::<i64>(0)?)).await.unwrap();
+    assert_eq!(values, [1, 2]);
+}
+
+#[tokio::test]
+async fn max_rows_truncates_every_row_helper() {
+    let dir = TempDir::new();
+    let conn = limited(&dir, true).await;
+    let sql = "SELECT x FROM t ORDER BY x;";
+
+    let values = query::query_map(&conn, sql, (), |row| Ok(row.get::<i64>(0)?)).await.unwrap();
+    assert_eq!(values, [1, 2]);
+
+    let rows: Vec<_> = query::query_stream(&conn, sql, ()).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(rows.len(), 2);
+
+    let rows: Vec<_> = conn.query_stream(sql, ()).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+#[tokio::test]
+async fn max_parameters_is_checked_by_the_wrapper_and_the_helpers() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).max_parameters(2).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (a, b, c);").await.unwrap();
+    let sql = "INSERT INTO t VALUES (?1, ?2, ?3);";
+
+    let err = conn.execute(sql, [1, 2, 3]).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::TooManyParameters(3, 2)), "{}", err);
+    let err = query::execute(&conn, sql, [1, 2, 3]).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::TooManyParameters(3, 2)), "{}", err);
+    let err = query::insert(&conn, sql, [1, 2, 3]).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::TooManyParameters(3, 2)), "{}", err);
+    let err = query::insert_many(&conn, sql, [[1, 2, 3]]).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::TooManyParameters(3, 2)), "{}", err);
+
+    query::execute(&conn, "INSERT INTO t (a, b) VALUES (?1, ?2);", [1, 2]).await.unwrap();
+    assert_eq!(query::query_scalar::<i64>(&conn, "SELECT count(*) FROM t;", ()).await.unwrap(), Some(1));
+}
+
+#[tokio::test]
+async fn query_stream_yields_every_row_in_order() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1), (2), (3);").await.unwrap();
+
+    let values: Vec<i64> = query::query_stream(&conn, "SELECT x FROM t ORDER BY x DESC;", ())
+        .await
+        .unwrap()
+        .map_ok(|row| row.get::<i64>(0).unwrap())
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(values, [3, 2, 1]);
+
+    let err = query::query_stream(&conn, "SELECT x FROM missing;", ()).await.err().unwrap();
+    assert!(err.as_libsql().is_some(), "{}", err);
+}
+
+#[tokio::test]
+async fn params_binds_named_and_positional_parameters() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE users (id INTEGER, name TEXT);").await.unwrap();
+
+    conn.execute("INSERT INTO users VALUES (:id, :name);", params! { ":id" => 1, ":name" => "alice" }).await.unwrap();
+    conn.execute("INSERT INTO users VALUES (?1, ?2);", params![2, "bob"]).await.unwrap();
+    conn.execute("DELETE FROM users WHERE name IS NULL;", params!()).await.unwrap();
+
+    let names = query::query_map(&conn, "SELECT name FROM users ORDER BY id;", (), |row| Ok(row.get::<String>(0)?)).await.unwrap();
+    assert_eq!(names, ["alice", "bob"]);
+}
+
+#[cfg(feature = "serde_json")]
+#[tokio::test]
+async fn query_json_maps_every_value_type() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+
+    let rows = query::query_json(&conn, "SELECT 1 AS i, 1.5 AS r, 'a' AS t, NULL AS n, x'0102' AS b;", ()).await.unwrap();
+    assert_eq!(rows, [serde_json::json!({ "i": 1, "r": 1.5, "t": "a", "n": null, "b": "AQI=" })]);
+}
+
+#[tokio::test]
+async fn query_scalar_reads_the_first_column_of_the_first_row() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x TEXT); INSERT INTO t VALUES ('a'), ('b');").await.unwrap();
+
+    assert_eq!(query::query_scalar::<i64>(&conn, "SELECT count(*) FROM t;", ()).await.unwrap(), Some(2));
+    assert_eq!(query::query_scalar::<String>(&conn, "SELECT x FROM t ORDER BY x;", ()).await.unwrap().as_deref(), Some("a"));
+    assert_eq!(query::query_scalar::<String>(&conn, "SELECT x FROM t WHERE x = 'c';", ()).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn query_scalar_tells_null_apart_from_no_rows() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (id INTEGER);").await.unwrap();
+
+    let sql = "SELECT max(id) FROM t;";
+    assert_eq!(query::query_scalar::<Option<i64>>(&conn, sql, ()).await.unwrap(), Some(None));
+    let err = query::query_scalar::<i64>(&conn, sql, ()).await.unwrap_err();
+    assert!(matches!(&err, ConnectionManagerError::NullValue(ty) if ty == "i64"), "{}", err);
+    assert!(err.to_string().contains("use `Option<i64>`"), "{}", err);
+
+    assert_eq!(query::query_scalar::<Option<i64>>(&conn, "SELECT id FROM t;", ()).await.unwrap(), None);
+    conn.execute("INSERT INTO t VALUES (3);", ()).await.unwrap();
+    assert_eq!(query::query_scalar::<Option<i64>>(&conn, sql, ()).await.unwrap(), Some(Some(3)));
+
+    // A non-NULL value of the wrong type is still a conversion error.
+    let err = query::query_scalar::<i64>(&conn, "SELECT 'text';", ()).await.unwrap_err();
+    assert!(!matches!(err, ConnectionManagerError::NullValue(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn execute_returns_the_affected_row_count() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x);").await.unwrap();
+
+    assert_eq!(query::execute(&conn, "INSERT INTO t VALUES (1), (2), (3);", ()).await.unwrap(), 3);
+    assert_eq!(query::execute(&conn, "UPDATE t SET x = x + 1 WHERE x > ?1;", [1]).await.unwrap(), 2);
+    assert_eq!(query::execute(&conn, "DELETE FROM t WHERE x > 10;", ()).await.unwrap(), 0);
+    let err = query::execute(&conn, "INSERT INTO missing VALUES (1);", ()).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::LibsqlError(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn insert_returns_the_rowid_of_its_own_connection() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    let other = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT);").await.unwrap();
+
+    assert_eq!(query::insert(&conn, "INSERT INTO users (name) VALUES (?1);", ["alice"]).await.unwrap(), 1);
+    assert_eq!(query::insert(&other, "INSERT INTO users (name) VALUES (?1);", ["bob"]).await.unwrap(), 2);
+    assert_eq!(conn.last_insert_rowid(), 1);
+    assert_eq!(query::insert(&conn, "INSERT INTO users (name) VALUES (?1);", ["carol"]).await.unwrap(), 3);
+}
+
+#[derive(Debug, PartialEq)]
+struct User {
+    id: i64,
+    name: String,
+    email: Option<String>,
+    score: f64,
+    active: bool,
+    avatar: Vec<u8>,
+}
+
+impl FromLibsqlRow for User {
+    fn from_row(row: &libsql::Row) -> Result<Self, ConnectionManagerError> {
+        Ok(User {
+            id: column(row, "id")?,
+            name: column(row, "name")?,
+            email: column(row, "email")?,
+            score: column(row, "score")?,
+            active: column(row, "active")?,
+            avatar: column(row, "avatar")?,
+        })
+    }
+}
+
+#[tokio::test]
+async fn query_as_maps_rows_into_structs() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("
+        CREATE TABLE users (id INTEGER, name TEXT, email TEXT, score REAL, active INTEGER, avatar BLOB);
+        INSERT INTO users VALUES (1, 'alice', 'alice@example.com', 9.5, 1, x'01'), (2, 'bob', NULL, 3, 0, x'0203');
+    ").await.unwrap();
+
+    // Columns are looked up by name, so their order does not matter.
+    let users = query::query_as::<User>(&conn, "SELECT avatar, active, score, email, name, id FROM users ORDER BY id;", ())
+        .await
+        .unwrap();
+    assert_eq!(users, [
+        User { id: 1, name: "alice".to_string(), email: Some("alice@example.com".to_string()), score: 9.5, active: true, avatar: vec![1] },
+        User { id: 2, name: "bob".to_string(), email: None, score: 3.0, active: false, avatar: vec![2, 3] },
+    ]);
+
+    let err = query::query_as::<User>(&conn, "SELECT id FROM users;", ()).await.unwrap_err();
+    assert!(err.to_string().contains("name"), "{}", err);
+}
+
+#[tokio::test]
+async fn column_metadata_is_available_before_reading_rows() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (a INTEGER, b TEXT);").await.unwrap();
+
+    let rows = conn.query("SELECT a, b AS label FROM t;", ()).await.unwrap();
+    assert_eq!(query::column_names(&rows), ["a", "label"]);
+
+    let columns = query::query_columns(&conn, "SELECT a, b, a + 1 AS c FROM t;").await.unwrap();
+    assert_eq!(columns, [
+        query::ResultColumn { name: "a".to_string(), decl_type: Some("INTEGER".to_string()) },
+        query::ResultColumn { name: "b".to_string(), decl_type: Some("TEXT".to_string()) },
+        query::ResultColumn { name: "c".to_string(), decl_type: None },
+    ]);
+}
+
+#[cfg(feature = "csv")]
+#[tokio::test]
+async fn query_csv_exports_a_header_and_every_row() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("
+        CREATE TABLE t (id INTEGER, name TEXT, score REAL, data BLOB);
+        INSERT INTO t VALUES (1, 'plain', 1.5, x'00ff'), (2, 'has, comma', NULL, NULL);
+    ").await.unwrap();
+
+    let mut out = Vec::new();
+    query::query_csv(&conn, "SELECT * FROM t ORDER BY id;", (), &mut out).await.unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "id,name,score,data\n1,plain,1.5,00ff\n2,\"has, comma\",,\n");
+}
+
+#[cfg(feature = "cancellation")]
+#[tokio::test]
+async fn query_cancellable_returns_promptly_when_cancelled() {
+    use std::time::{Duration, Instant};
+
+    use tokio_util::sync::CancellationToken;
+
+    struct Number(i64);
+
+    impl FromLibsqlRow for Number {
+        fn from_row(row: &libsql::Row) -> Result<Self, ConnectionManagerError> {
+            Ok(Number(column(row, "n")?))
+        }
+    }
+
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    // Takes far longer than the test to produce every row.
+    let slow = "WITH RECURSIVE c(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM c) SELECT n FROM c LIMIT 1000000000;";
+
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        canceller.cancel();
+    });
+    let started = Instant::now();
+    let result = query::query_cancellable::<Number>(&conn, slow, (), &token).await;
+    assert!(matches!(result, Err(ConnectionManagerError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+
+    // The connection is usable afterwards, and a finished query is unaffected.
+    let numbers = query::query_cancellable::<Number>(&conn, "SELECT 7 AS n;", (), &CancellationToken::new()).await.unwrap();
+    assert_eq!(numbers.iter().map(|number| number.0).collect::<Vec<_>>(), [7]);
+}
+
+#[tokio::test]
+async fn changes_and_total_changes_count_batch_writes() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (x);").await.unwrap();
+
+    let before = conn.total_changes();
+    conn.execute_batch("INSERT INTO t VALUES (1), (2), (3); DELETE FROM t WHERE x = 1;").await.unwrap();
+    assert_eq!(conn.total_changes() - before, 4);
+    assert_eq!(conn.changes(), 1);
+
+    let changed = query::execute_batch(&conn, "UPDATE t SET x = x * 10; INSERT INTO t VALUES (4);").await.unwrap();
+    assert_eq!(changed, 3);
+    assert_eq!(conn.changes(), 1);
+}
+
+#[tokio::test]
+async fn explain_shows_the_index_a_query_uses() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT); CREATE INDEX users_email ON users (email);")
+        .await
+        .unwrap();
+
+    let plan = query::explain(&conn, "SELECT id FROM users WHERE email = ?1;", params!["a@example.com"]).await.unwrap();
+    assert!(!plan.is_empty());
+    assert!(plan.iter().any(|step| step.detail.contains("USING COVERING INDEX users_email") || step.detail.contains("USING INDEX users_email")), "{:?}", plan);
+    assert_eq!(plan[0].parent, 0);
+}
+
+#[tokio::test]
+async fn query_map_collects_rows_into_tuples_and_stops_at_an_error() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let conn = manager.connect().await.unwrap();
+    conn.execute_batch("CREATE TABLE t (id INTEGER, name TEXT); INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, NULL);")
+        .await
+        .unwrap();
+
+    let sql = "SELECT id, name FROM t WHERE name IS NOT NULL ORDER BY id;";
+    let pairs = query::query_map(&conn, sql, (), |row| Ok((row.get::<i64>(0)?, row.get::<String>(1)?))).await.unwrap();
+    assert_eq!(pairs, [(1, "a".to_string()), (2, "b".to_string())]);
+
+    let mut mapped = 0;
+    let sql = "SELECT id, name FROM t ORDER BY id;";
+    let result = query::query_map(&conn, sql, (), |row| {
+        mapped += 1;
+        Ok((row.get::<i64>(0)?, row.get::<String>(1)?))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(mapped, 3);
+}
@@ -0,0 +1,165 @@
+//! Tests against a live sqld. Run them with
+//! `LIBSQL_TEST_URL=http://127.0.0.1:8080 cargo test -- --ignored`.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::pool::ReadWriteSplit;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, server, TempDir};
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn prewarm_replica_syncs_once_the_replica_is_in_use() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+
+    let writer = primary.connect().await.unwrap();
+    writer.execute_batch("DROP TABLE IF EXISTS prewarm; CREATE TABLE prewarm (x);").await.unwrap();
+    let reader = replica.connect().await.unwrap();
+
+    writer.execute("INSERT INTO prewarm VALUES (1);", ()).await.unwrap();
+    replica.prewarm_replica().await.unwrap();
+    assert_eq!(scalar(&reader, "SELECT count(*) FROM prewarm;").await, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn catch_up_then_offline_only_syncs_on_demand() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    let writer = primary.connect().await.unwrap();
+    writer.execute_batch("DROP TABLE IF EXISTS offline; CREATE TABLE offline (x);").await.unwrap();
+
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_millis(50))
+        .catch_up_then_offline(true)
+        .build()
+        .unwrap();
+    let reader = replica.connect().await.unwrap();
+    writer.execute("INSERT INTO offline VALUES (1);", ()).await.unwrap();
+
+    // Well past the sync interval, which the replica ignores.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(scalar(&reader, "SELECT count(*) FROM offline;").await, 0);
+
+    replica.sync().await.unwrap();
+    assert_eq!(scalar(&reader, "SELECT count(*) FROM offline;").await, 1);
+}
+
+/// Builds a pool of four replica connections concurrently and returns how
+/// many syncs reported progress while warming it up.
+async fn warmup_syncs(dedup: bool) -> usize {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let syncs = Arc::new(AtomicUsize::new(0));
+    let counter = syncs.clone();
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .dedup_initial_sync(dedup)
+        .sync_progress(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    let _conns = futures::future::try_join_all((0..4).map(|_| replica.connect())).await.unwrap();
+    syncs.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn dedup_initial_sync_syncs_once_for_concurrent_connects() {
+    assert_eq!(warmup_syncs(true).await, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn without_dedup_every_connect_syncs() {
+    assert_eq!(warmup_syncs(false).await, 1 + 4);
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn replica_lag_reports_the_applied_frame() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+
+    let writer = primary.connect().await.unwrap();
+    writer.execute_batch("DROP TABLE IF EXISTS lag; CREATE TABLE lag (x);").await.unwrap();
+    let synced = replica.sync().await.unwrap();
+    assert_eq!(replica.replica_lag().await.unwrap(), synced.frame_no());
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn concurrent_connects_all_see_the_initial_sync() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    primary.connect().await.unwrap()
+        .execute_batch("DROP TABLE IF EXISTS startup; CREATE TABLE startup (x); INSERT INTO startup VALUES (1);")
+        .await
+        .unwrap();
+
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    let conns = futures::future::try_join_all((0..8).map(|_| replica.connect())).await.unwrap();
+    for conn in &conns {
+        assert_eq!(scalar(conn, "SELECT count(*) FROM startup;").await, 1);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn read_write_split_reads_a_write_after_a_sync() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    let split = ReadWriteSplit::new(replica, primary).await.unwrap();
+
+    split.write().await.unwrap().execute_batch("DROP TABLE IF EXISTS split; CREATE TABLE split (x);").await.unwrap();
+    split.sync().await.unwrap();
+    split.write().await.unwrap().execute("INSERT INTO split VALUES (1);", ()).await.unwrap();
+    assert_eq!(scalar(&split.read().await.unwrap(), "SELECT count(*) FROM split;").await, 0);
+
+    split.sync().await.unwrap();
+    assert_eq!(scalar(&split.read().await.unwrap(), "SELECT count(*) FROM split;").await, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn backup_to_copies_what_a_replica_synced() {
+    let (url, token) = server();
+    let dir = TempDir::new();
+    let primary = LibsqlConnectionManager::remote(&url, &token).build().unwrap();
+    primary.connect().await.unwrap()
+        .execute_batch("DROP TABLE IF EXISTS backup; CREATE TABLE backup (x); INSERT INTO backup VALUES (1), (2);")
+        .await
+        .unwrap();
+    let replica = LibsqlConnectionManager::remote_replica(dir.join("replica.db"), &url, &token, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    replica.connect().await.unwrap();
+
+    let dest = dir.join("backup.sqlite");
+    replica.backup_to(&dest).await.unwrap();
+    let copy = LibsqlConnectionManager::local(&dest).build().unwrap().connect().await.unwrap();
+    assert_eq!(scalar(&copy, "SELECT count(*) FROM backup;").await, 2);
+}
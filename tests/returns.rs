@@ -0,0 +1,87 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, TempDir};
+
+#[tokio::test]
+async fn connections_returned_mid_transaction_are_dropped() {
+    let dir = TempDir::new();
+    let leaks = Arc::new(AtomicUsize::new(0));
+    let counter = leaks.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_open_transaction(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    let pool = bb8::Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    {
+        let conn = pool.get().await.unwrap();
+        conn.execute_batch("CREATE TABLE t (x); BEGIN; INSERT INTO t VALUES (1);").await.unwrap();
+    }
+    assert_eq!(leaks.load(Ordering::SeqCst), 1);
+
+    let conn = pool.get().await.unwrap();
+    assert!(conn.is_autocommit());
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 0);
+}
+
+#[tokio::test]
+async fn committed_connections_are_kept() {
+    let dir = TempDir::new();
+    let leaks = Arc::new(AtomicUsize::new(0));
+    let counter = leaks.clone();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .on_open_transaction(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    let pool = bb8::Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    {
+        let conn = pool.get().await.unwrap();
+        conn.execute_batch("BEGIN; CREATE TEMP TABLE scratch (x); COMMIT;").await.unwrap();
+    }
+    assert_eq!(leaks.load(Ordering::SeqCst), 0);
+
+    // The same connection comes back, temp table and all.
+    let conn = pool.get().await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM temp.sqlite_master WHERE name = 'scratch';").await, 1);
+}
+
+#[tokio::test]
+async fn reset_on_return_drops_temp_tables() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).reset_on_return(true).build().unwrap();
+    let pool = bb8::Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    {
+        let conn = pool.get().await.unwrap();
+        conn.execute_batch("CREATE TEMP TABLE scratch (x); CREATE TEMP VIEW scratch_view AS SELECT 1;").await.unwrap();
+    }
+
+    let conn = pool.get().await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM temp.sqlite_master;").await, 0);
+}
+
+#[tokio::test]
+async fn a_returned_transaction_does_not_lock_out_other_connections() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).reset_on_return(true).build().unwrap();
+    let pool = bb8::Pool::builder().max_size(2).build(manager).await.unwrap();
+    pool.get().await.unwrap().execute_batch("CREATE TABLE t (x);").await.unwrap();
+
+    let writer = pool.get().await.unwrap();
+    let other = pool.get().await.unwrap();
+    writer.execute_batch("BEGIN IMMEDIATE; INSERT INTO t VALUES (1);").await.unwrap();
+    drop(writer);
+
+    other.execute("INSERT INTO t VALUES (2);", ()).await.unwrap();
+    assert_eq!(scalar(&other, "SELECT count(*) FROM t;").await, 1);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use bb8::Pool;
+use bb8_libsql::schema::{Schema, SchemaCache};
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::TempDir;
+
+const SCHEMA: &str = "
+    CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);
+    CREATE TABLE posts (id INTEGER PRIMARY KEY AUTOINCREMENT, user_id INTEGER, body TEXT);
+    CREATE INDEX posts_user ON posts (user_id);
+    CREATE VIEW user_posts AS SELECT users.email, posts.body FROM users JOIN posts ON posts.user_id = users.id;
+    CREATE TRIGGER users_delete AFTER DELETE ON users BEGIN DELETE FROM posts WHERE user_id = old.id; END;
+";
+
+async fn pool(dir: &TempDir) -> Pool<LibsqlConnectionManager> {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let pool = Pool::builder().max_size(1).build(manager).await.unwrap();
+    pool.get().await.unwrap().execute_batch(SCHEMA).await.unwrap();
+    pool
+}
+
+#[tokio::test]
+async fn load_reads_every_kind_of_object() {
+    let dir = TempDir::new();
+    let pool = pool(&dir).await;
+    let schema = Schema::load(&pool.get().await.unwrap()).await.unwrap();
+
+    let tables = schema.tables.iter().map(|table| table.name.as_str()).collect::<Vec<_>>();
+    // `sqlite_sequence`, created for AUTOINCREMENT, is left out.
+    assert_eq!(tables, ["users", "posts"]);
+    assert!(schema.table("USERS").unwrap().sql.as_deref().unwrap().starts_with("CREATE TABLE users"));
+    assert!(schema.table("missing").is_none());
+
+    assert_eq!(schema.index("posts_user").unwrap().table, "posts");
+    assert_eq!(schema.indexes_on("users").count(), 1);
+    assert!(schema.indexes_on("users").next().unwrap().sql.is_none());
+    assert_eq!(schema.view("user_posts").unwrap().table, "user_posts");
+    assert_eq!(schema.trigger("users_delete").unwrap().table, "users");
+}
+
+#[tokio::test]
+async fn the_cache_keeps_the_schema_until_invalidated() {
+    let dir = TempDir::new();
+    let pool = pool(&dir).await;
+    let cache = SchemaCache::new();
+
+    let first = cache.get(&pool).await.unwrap();
+    pool.get().await.unwrap().execute_batch("CREATE TABLE tags (name TEXT);").await.unwrap();
+    assert!(cache.get(&pool).await.unwrap().table("tags").is_none());
+
+    cache.invalidate().await;
+    let reloaded = cache.get(&pool).await.unwrap();
+    assert!(reloaded.table("tags").is_some());
+    assert_eq!(reloaded.tables.len(), first.tables.len() + 1);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use bb8::Pool;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::pool::ensure_schema_version;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, TempDir};
+
+const MIGRATIONS: [(i32, &str); 3] = [
+    (1, "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);"),
+    (2, "INSERT INTO users (name) VALUES ('admin');"),
+    (3, "ALTER TABLE users ADD COLUMN email TEXT;"),
+];
+
+async fn pool(dir: &TempDir) -> Pool<LibsqlConnectionManager> {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .init_step("busy_timeout", |conn| async move {
+            conn.execute_batch("PRAGMA busy_timeout = 5000;").await?;
+            Ok(())
+        })
+        .build()
+        .unwrap();
+    Pool::builder().max_size(2).build(manager).await.unwrap()
+}
+
+#[tokio::test]
+async fn a_fresh_database_gets_every_migration() {
+    let dir = TempDir::new();
+    let pool = pool(&dir).await;
+
+    ensure_schema_version(&pool, 3, &MIGRATIONS).await.unwrap();
+
+    let conn = pool.get().await.unwrap();
+    assert_eq!(scalar(&conn, "PRAGMA user_version;").await, 3);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM users WHERE email IS NULL;").await, 1);
+}
+
+#[tokio::test]
+async fn a_partially_migrated_database_gets_the_rest() {
+    let dir = TempDir::new();
+    let pool = pool(&dir).await;
+
+    ensure_schema_version(&pool, 2, &MIGRATIONS).await.unwrap();
+    assert_eq!(scalar(&pool.get().await.unwrap(), "PRAGMA user_version;").await, 2);
+
+    ensure_schema_version(&pool, 3, &MIGRATIONS).await.unwrap();
+    let conn = pool.get().await.unwrap();
+    assert_eq!(scalar(&conn, "PRAGMA user_version;").await, 3);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM users;").await, 1);
+}
+
+#[tokio::test]
+async fn a_newer_database_is_refused() {
+    let dir = TempDir::new();
+    let pool = pool(&dir).await;
+    ensure_schema_version(&pool, 3, &MIGRATIONS).await.unwrap();
+
+    let err = ensure_schema_version(&pool, 2, &MIGRATIONS).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn instances_migrating_together_apply_each_migration_once() {
+    let dir = TempDir::new();
+    let mut pools = Vec::new();
+    for _ in 0..4 {
+        pools.push(pool(&dir).await);
+    }
+
+    let runs: Vec<_> = pools.iter()
+        .cloned()
+        .map(|pool| tokio::spawn(async move { ensure_schema_version(&pool, 3, &MIGRATIONS).await }))
+        .collect();
+    for run in runs {
+        run.await.unwrap().unwrap();
+    }
+
+    let conn = pools[0].get().await.unwrap();
+    assert_eq!(scalar(&conn, "PRAGMA user_version;").await, 3);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM users;").await, 1);
+}
+
+#[tokio::test]
+async fn replicas_are_refused() {
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local_replica(dir.join("replica.db"));
+    let pool = Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    let err = ensure_schema_version(&pool, 1, &MIGRATIONS).await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
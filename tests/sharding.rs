@@ -0,0 +1,49 @@
+mod common;
+
+use bb8::Pool;
+use bb8_libsql::pool::ShardedPool;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::TempDir;
+
+// Computed independently from FNV-1a and the MurmurHash3 finalizer; a change
+// here moves keys to other shards.
+const ROUTES: [usize; 5] = [2, 3, 1, 3, 3];
+
+const ID_ROUTES: [usize; 4] = [3, 3, 1, 2];
+
+fn sharded(dir: &TempDir, shards: usize) -> ShardedPool {
+    let pools = (0..shards)
+        .map(|idx| Pool::builder().build_unchecked(LibsqlConnectionManager::local(dir.join(&format!("shard-{}.db", idx)))))
+        .collect();
+    ShardedPool::new(pools).unwrap()
+}
+
+#[tokio::test]
+async fn routing_is_pinned() {
+    let dir = TempDir::new();
+    let pool = sharded(&dir, 4);
+
+    let routes: Vec<_> = ["", "a", "user-1", "user-2", "tenant/acme"].iter()
+        .map(|key| pool.shard_for(*key))
+        .collect();
+    assert_eq!(routes, ROUTES);
+
+    let ids: Vec<_> = [1u64, 2, 3, 1_000_000].iter()
+        .map(|id| pool.shard_for(&id.to_be_bytes()))
+        .collect();
+    assert_eq!(ids, ID_ROUTES);
+}
+
+#[tokio::test]
+async fn equal_bytes_route_together() {
+    let dir = TempDir::new();
+    let pool = sharded(&dir, 7);
+    assert_eq!(pool.shard_for("user-1"), pool.shard_for(&String::from("user-1")));
+    assert_eq!(pool.shard_for("user-1"), pool.shard_for(b"user-1".as_slice()));
+}
+
+#[test]
+fn a_sharded_pool_needs_a_shard() {
+    assert!(ShardedPool::new(Vec::new()).is_err());
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bb8::ManageConnection;
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{TempDir, TOKEN, URL};
+
+/// What sqld's HTTP API answers a request with an expired or invalid token.
+fn unauthorized() -> ConnectionManagerError {
+    libsql::Error::Hrana("api error: `Unauthorized`".into()).into()
+}
+
+#[tokio::test]
+async fn unauthorized_connects_refresh_the_token_and_retry() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+    let seen = tokens.clone();
+    let rejected = Arc::new(AtomicBool::new(false));
+    let manager = LibsqlConnectionManager::remote_with(URL, TOKEN, move |_url, token| {
+        seen.lock().unwrap().push(token);
+        let path = path.clone();
+        async move { libsql::Builder::new_local(path).build().await }
+    })
+    .token_provider(|| async { Ok("fresh".to_string()) })
+    .init_step("auth", move |_conn| {
+        let rejected = rejected.clone();
+        async move {
+            if rejected.swap(true, Ordering::SeqCst) {
+                return Ok(());
+            }
+            Err(unauthorized())
+        }
+    })
+    .build()
+    .unwrap();
+
+    manager.connect().await.unwrap();
+    assert_eq!(*tokens.lock().unwrap(), ["token", "fresh"]);
+}
+
+#[tokio::test]
+async fn unauthorized_connects_fail_without_a_token_provider() {
+    let dir = TempDir::new();
+    let path = dir.join("db.sqlite");
+    let manager = LibsqlConnectionManager::remote_with(URL, TOKEN, move |_url, _token| {
+        let path = path.clone();
+        async move { libsql::Builder::new_local(path).build().await }
+    })
+    .init_step("auth", |_conn| async {
+        Err(unauthorized())
+    })
+    .build()
+    .unwrap();
+
+    let err = manager.connect().await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::InitStep(..)), "{}", err);
+    assert!(err.is_unauthorized());
+}
+
+#[test]
+fn build_rejects_a_missing_token_file() {
+    let dir = TempDir::new();
+    let err = LibsqlConnectionManager::remote_from_file(URL, dir.join("token")).build().unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::Config(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn the_token_file_is_read_when_the_database_is_built() {
+    let dir = TempDir::new();
+    let token_path = dir.join("token");
+    std::fs::write(&token_path, "token").unwrap();
+    let manager = LibsqlConnectionManager::remote_from_file(URL, &token_path).build().unwrap();
+
+    std::fs::remove_file(&token_path).unwrap();
+    let err = manager.connect().await.unwrap_err();
+    assert!(matches!(err, ConnectionManagerError::IoError(_)), "{}", err);
+
+    std::fs::write(&token_path, "  rotated\n").unwrap();
+    manager.connect().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs a sqld at LIBSQL_TEST_URL"]
+async fn a_token_file_authenticates_against_a_live_server() {
+    let (url, token) = common::server();
+    let dir = TempDir::new();
+    let token_path = dir.join("token");
+    std::fs::write(&token_path, format!("{}\n", token)).unwrap();
+
+    let manager = LibsqlConnectionManager::remote_from_file(&url, &token_path).validate_on_create(true).build().unwrap();
+    manager.connect().await.unwrap();
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use bb8::ErrorSink;
+use bb8_libsql::errors::{ConnectionManagerError, TracingErrorSink};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A span's name and its `field=value` pairs.
+type RecordedSpan = (String, Vec<String>);
+
+/// Records every span and event as its name or message followed by its
+/// `field=value` pairs.
+#[derive(Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    events: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+struct Fields<'a>(&'a mut Vec<String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push(format!("{}={}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Vec::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name().to_string(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Vec::new();
+        event.record(&mut Fields(&mut fields));
+        self.events.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn the_tracing_error_sink_logs_errors() {
+    let recorder = Recorder::default();
+    let events = recorder.events.clone();
+    tracing::subscriber::with_default(recorder, || {
+        let sink = TracingErrorSink.boxed_clone();
+        sink.sink(ConnectionManagerError::Config("bad url".to_string()));
+    });
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(events[0].contains(&"error=Config Error: bad url".to_string()), "{:?}", events);
+}
+
+#[tokio::test]
+async fn connect_records_the_connection_label() {
+    use bb8::ManageConnection;
+
+    let dir = common::TempDir::new();
+    let manager = bb8_libsql::LibsqlConnectionManager::local(dir.join("db.sqlite"))
+        .connection_label("tenant-42")
+        .build()
+        .unwrap();
+
+    let recorder = Recorder::default();
+    let spans = recorder.spans.clone();
+    let _guard = tracing::subscriber::set_default(recorder);
+    manager.connect().await.unwrap();
+
+    let spans = spans.lock().unwrap();
+    let (_, fields) = spans.iter().find(|(name, _)| name == "libsql.connect").expect("no libsql.connect span");
+    assert!(fields.contains(&"label=tenant-42".to_string()), "{:?}", fields);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bb8::ManageConnection;
+use bb8_libsql::connection::LibsqlConnection;
+use bb8_libsql::query::{self, TransactionBehavior};
+use bb8_libsql::LibsqlConnectionManager;
+
+use common::{scalar, TempDir};
+
+/// Returns two connections to one database with a table `t (x)`. The second
+/// reports lock conflicts right away instead of waiting.
+async fn writers(dir: &TempDir) -> (LibsqlConnection, LibsqlConnection) {
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let first = manager.connect().await.unwrap();
+    let second = manager.connect().await.unwrap();
+    first.execute_batch("CREATE TABLE t (x);").await.unwrap();
+    second.execute_batch("PRAGMA busy_timeout = 0;").await.unwrap();
+    (first, second)
+}
+
+#[tokio::test]
+async fn transaction_retry_retries_busy_transactions_until_they_commit() {
+    let dir = TempDir::new();
+    let (holder, conn) = writers(&dir).await;
+    holder.execute_batch("BEGIN IMMEDIATE;").await.unwrap();
+    let release = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        holder.execute_batch("COMMIT;").await.unwrap();
+    });
+
+    let runs = Arc::new(AtomicU32::new(0));
+    let inserted = query::transaction_retry(&conn, 20, |tx| {
+        let runs = runs.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tx.execute("INSERT INTO t VALUES (1);", ()).await
+        }
+    }).await.unwrap();
+    release.await.unwrap();
+
+    assert_eq!(inserted, 1);
+    // Only BEGIN IMMEDIATE was busy, so the closure ran once it got the lock.
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1);
+}
+
+#[tokio::test]
+async fn transaction_retry_rolls_back_a_busy_attempt_before_running_again() {
+    let dir = TempDir::new();
+    let (conn, _) = writers(&dir).await;
+
+    let runs = Arc::new(AtomicU32::new(0));
+    query::transaction_retry(&conn, 3, |tx| {
+        let runs = runs.clone();
+        async move {
+            tx.execute("INSERT INTO t VALUES (1);", ()).await?;
+            if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(libsql::Error::SqliteFailure(5, "database is locked".to_string()).into());
+            }
+            Ok(())
+        }
+    }).await.unwrap();
+
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1);
+}
+
+#[tokio::test]
+async fn transaction_retry_gives_up_after_max_retries() {
+    let dir = TempDir::new();
+    let (holder, conn) = writers(&dir).await;
+    holder.execute_batch("BEGIN IMMEDIATE;").await.unwrap();
+
+    let runs = Arc::new(AtomicU32::new(0));
+    let err = query::transaction_retry(&conn, 2, |tx| {
+        let runs = runs.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tx.execute("INSERT INTO t VALUES (1);", ()).await
+        }
+    }).await.unwrap_err();
+
+    assert!(err.is_busy(), "{}", err);
+    // BEGIN IMMEDIATE itself is busy, so the closure never runs.
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+    assert!(conn.is_autocommit());
+    holder.execute_batch("COMMIT;").await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 0);
+}
+
+#[tokio::test]
+async fn insert_many_inserts_every_row() {
+    let dir = TempDir::new();
+    let (conn, _) = writers(&dir).await;
+
+    let inserted = query::insert_many(&conn, "INSERT INTO t VALUES (?1);", (0..1000).map(|x| [x])).await.unwrap();
+    assert_eq!(inserted, 1000);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1000);
+    assert!(conn.is_autocommit());
+}
+
+#[tokio::test]
+async fn insert_many_rolls_back_on_a_mid_batch_error() {
+    let dir = TempDir::new();
+    let (conn, _) = writers(&dir).await;
+    conn.execute_batch("CREATE UNIQUE INDEX t_x ON t (x);").await.unwrap();
+
+    let rows = (0..1000).map(|x| [if x == 500 { 0 } else { x }]);
+    let err = query::insert_many(&conn, "INSERT INTO t VALUES (?1);", rows).await.unwrap_err();
+    assert!(err.to_string().contains("UNIQUE"), "{}", err);
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 0);
+    assert!(conn.is_autocommit());
+}
+
+#[tokio::test]
+async fn an_immediate_transaction_takes_the_write_lock_at_begin() {
+    let dir = TempDir::new();
+    let (first, second) = writers(&dir).await;
+
+    let tx = query::begin(&first, TransactionBehavior::Immediate).await.unwrap();
+    let err = second.execute("INSERT INTO t VALUES (1);", ()).await.unwrap_err();
+    assert!(err.is_busy(), "{}", err);
+    tx.rollback().await.unwrap();
+    second.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_deferred_transaction_takes_no_lock_until_it_writes() {
+    let dir = TempDir::new();
+    let (first, second) = writers(&dir).await;
+
+    let tx = query::begin(&first, TransactionBehavior::Deferred).await.unwrap();
+    second.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+    tx.execute("INSERT INTO t VALUES (2);", ()).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(scalar(&second, "SELECT count(*) FROM t;").await, 2);
+}
+
+#[tokio::test]
+async fn rollback_discards_and_commit_keeps_the_transaction_writes() {
+    let dir = TempDir::new();
+    let (conn, _) = writers(&dir).await;
+
+    let tx = query::begin(&conn, TransactionBehavior::Exclusive).await.unwrap();
+    tx.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 0);
+
+    let tx = query::begin(&conn, TransactionBehavior::Exclusive).await.unwrap();
+    tx.execute("INSERT INTO t VALUES (1);", ()).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(scalar(&conn, "SELECT count(*) FROM t;").await, 1);
+}
+
+#[tokio::test]
+async fn begin_snapshot_hides_writes_committed_after_it_starts() {
+    let dir = TempDir::new();
+    let (writer, reader) = writers(&dir).await;
+    writer.execute_batch("PRAGMA journal_mode = WAL; INSERT INTO t VALUES (1);").await.unwrap();
+
+    let snapshot = query::begin_snapshot(&reader).await.unwrap();
+    // Committed before the snapshot's first query, which must not see it.
+    writer.execute("INSERT INTO t VALUES (2);", ()).await.unwrap();
+    assert_eq!(scalar(&snapshot, "SELECT count(*) FROM t;").await, 1);
+    writer.execute("INSERT INTO t VALUES (3);", ()).await.unwrap();
+    assert_eq!(scalar(&snapshot, "SELECT sum(x) FROM t;").await, 1);
+
+    assert!(snapshot.execute("INSERT INTO t VALUES (4);", ()).await.is_err());
+    snapshot.commit().await.unwrap();
+    assert_eq!(scalar(&reader, "SELECT count(*) FROM t;").await, 3);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use bb8_libsql::errors::ConnectionManagerError;
+use bb8_libsql::value::{FromValue, ToValue, ValueExt};
+use libsql::Value;
+
+fn is_invalid_type<T>(result: Result<T, ConnectionManagerError>) -> bool {
+    matches!(result.err().as_ref().and_then(ConnectionManagerError::as_libsql), Some(libsql::Error::InvalidColumnType))
+}
+
+fn is_null_value<T>(result: Result<T, ConnectionManagerError>) -> bool {
+    matches!(result.err().as_ref().and_then(ConnectionManagerError::as_libsql), Some(libsql::Error::NullValue))
+}
+
+#[test]
+fn values_convert_to_matching_rust_types() {
+    assert_eq!(Value::Integer(-3).get::<i64>().unwrap(), -3);
+    assert_eq!(Value::Integer(-3).get::<i32>().unwrap(), -3);
+    assert_eq!(Value::Integer(3).get::<u32>().unwrap(), 3);
+    assert_eq!(Value::Integer(i64::MAX).get::<u64>().unwrap(), i64::MAX as u64);
+    assert_eq!(Value::Real(1.5).get::<f64>().unwrap(), 1.5);
+    assert_eq!(Value::Integer(2).get::<f64>().unwrap(), 2.0);
+    assert!(Value::Integer(1).get::<bool>().unwrap());
+    assert!(!Value::Integer(0).get::<bool>().unwrap());
+    assert_eq!(Value::Text("a".to_string()).into_typed::<String>().unwrap(), "a");
+    assert_eq!(Value::Blob(vec![1, 2]).get::<Vec<u8>>().unwrap(), [1, 2]);
+    assert_eq!(Value::Null.get::<Option<String>>().unwrap(), None);
+    assert_eq!(Value::Integer(4).get::<Option<i64>>().unwrap(), Some(4));
+    assert_eq!(Value::Real(1.0).get::<Value>().unwrap(), Value::Real(1.0));
+}
+
+#[test]
+fn mismatched_values_are_errors() {
+    assert!(is_invalid_type(Value::Text("1".to_string()).get::<i64>()));
+    assert!(is_invalid_type(Value::Integer(i64::from(i32::MAX) + 1).get::<i32>()));
+    assert!(is_invalid_type(Value::Integer(-1).get::<u32>()));
+    assert!(is_invalid_type(Value::Integer(-1).get::<u64>()));
+    assert!(is_invalid_type(Value::Integer(2).get::<bool>()));
+    assert!(is_invalid_type(Value::Text("1.5".to_string()).get::<f64>()));
+    assert!(is_invalid_type(Value::Blob(vec![0x61]).get::<String>()));
+    assert!(is_invalid_type(Value::Text("a".to_string()).get::<Vec<u8>>()));
+    assert!(is_invalid_type(Value::Text("a".to_string()).get::<Option<i64>>()));
+    assert!(is_null_value(Value::Null.get::<i64>()));
+    assert!(is_null_value(Value::Null.get::<String>()));
+    assert!(is_null_value(i64::from_value(Value::Null)));
+}
+
+#[test]
+fn rust_types_convert_to_values() {
+    assert_eq!(7i64.to_value().unwrap(), Value::Integer(7));
+    assert_eq!(7i32.to_value().unwrap(), Value::Integer(7));
+    assert_eq!(u32::MAX.to_value().unwrap(), Value::Integer(i64::from(u32::MAX)));
+    assert_eq!((i64::MAX as u64).to_value().unwrap(), Value::Integer(i64::MAX));
+    assert_eq!(0.5f64.to_value().unwrap(), Value::Real(0.5));
+    assert_eq!(true.to_value().unwrap(), Value::Integer(1));
+    assert_eq!("a".to_value().unwrap(), Value::Text("a".to_string()));
+    assert_eq!("a".to_string().to_value().unwrap(), Value::Text("a".to_string()));
+    assert_eq!(vec![1u8].to_value().unwrap(), Value::Blob(vec![1]));
+    assert_eq!([1u8, 2][..].to_value().unwrap(), Value::Blob(vec![1, 2]));
+    assert_eq!(None::<i64>.to_value().unwrap(), Value::Null);
+    assert_eq!(Some(1i64).to_value().unwrap(), Value::Integer(1));
+
+    let err = u64::MAX.to_value().unwrap_err();
+    assert!(err.to_string().contains("does not fit"), "{}", err);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn timestamps_convert_from_text_and_unix_seconds() {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+    let expected = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap().and_hms_opt(7, 8, 9).unwrap();
+    for text in ["2024-05-06 07:08:09", "2024-05-06T07:08:09", "2024-05-06T09:08:09+02:00", "2024-05-06 07:08:09.000"] {
+        assert_eq!(Value::Text(text.to_string()).get::<NaiveDateTime>().unwrap(), expected, "{}", text);
+    }
+    let seconds = expected.and_utc().timestamp();
+    assert_eq!(Value::Integer(seconds).get::<DateTime<Utc>>().unwrap(), expected.and_utc());
+    assert_eq!(Value::Text("2024-05-06".to_string()).get::<NaiveDate>().unwrap(), expected.date());
+    assert_eq!(expected.date().to_value().unwrap(), Value::Text("2024-05-06".to_string()));
+
+    assert!(is_invalid_type(Value::Text("yesterday".to_string()).get::<NaiveDateTime>()));
+    assert!(is_invalid_type(Value::Real(1.0).get::<NaiveDate>()));
+    assert!(is_null_value(Value::Null.get::<DateTime<Utc>>()));
+}
+
+#[cfg(feature = "chrono")]
+#[tokio::test]
+async fn timestamps_round_trip_through_a_pooled_connection() {
+    use bb8_libsql::value::{datetime_text, ToValue};
+    use bb8_libsql::{query, LibsqlConnectionManager};
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+    use common::TempDir;
+
+    let dir = TempDir::new();
+    let manager = LibsqlConnectionManager::local(dir.join("db.sqlite")).build().unwrap();
+    let pool = bb8::Pool::builder().max_size(1).build(manager).await.unwrap();
+    let conn = pool.get().await.unwrap();
+    conn.execute_batch("CREATE TABLE events (at TEXT);").await.unwrap();
+
+    let at = Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 58).unwrap() + chrono::Duration::milliseconds(125);
+    conn.execute("INSERT INTO events VALUES (?1);", [at.to_value().unwrap()]).await.unwrap();
+    let text = query::query_scalar::<String>(&conn, "SELECT at FROM events;", ()).await.unwrap().unwrap();
+    assert_eq!(text, datetime_text(&at));
+    assert_eq!(query::query_scalar::<DateTime<Utc>>(&conn, "SELECT at FROM events;", ()).await.unwrap(), Some(at));
+
+    // The text works with SQLite's date functions, and their output reads back.
+    let date = query::query_scalar::<NaiveDate>(&conn, "SELECT date(at) FROM events;", ()).await.unwrap();
+    assert_eq!(date, NaiveDate::from_ymd_opt(2024, 2, 29));
+    let next = query::query_scalar::<NaiveDateTime>(&conn, "SELECT datetime(at, '+2 seconds') FROM events;", ()).await.unwrap();
+    assert_eq!(next, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(0, 0, 0));
+
+    // Other offsets are stored as UTC.
+    let offset = chrono::FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 1, 1, 2, 0, 0).unwrap();
+    assert_eq!(datetime_text(&offset), "2024-01-01 00:00:00.000");
+}